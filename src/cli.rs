@@ -0,0 +1,301 @@
+use std::process::ExitCode;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::config::{AppConfig, SharedConfig};
+use crate::model::PrimitiveEvent;
+use crate::service::admin;
+use crate::service::feeds::{FeedService, ParsedCalendar};
+use crate::service::fetch;
+use crate::service::ics;
+use crate::service::json::EventDto;
+use crate::service::lint::{self, LintReport};
+use crate::service::range::{parse_datetime, parse_range, parse_timezone, RangeError};
+use crate::service::store::FeedStore;
+use crate::service::tokens::{self, DEFAULT_TOKEN_LENGTH};
+use crate::service::users;
+use crate::service::validation::MIN_TOKEN_LENGTH;
+use crate::Application;
+
+/// Length of range (in days) used when end is not specified
+const DEFAULT_RANGE_DAYS: i64 = 30;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Server is started when command is not specified
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start server
+    Serve,
+
+    /// Load config and check that it is valid
+    CheckConfig,
+
+    /// Generate random token for feed and its digest to store in config
+    GenToken {
+        /// Length of token
+        #[arg(long, default_value_t = DEFAULT_TOKEN_LENGTH, value_parser = parse_token_length)]
+        length: usize,
+    },
+
+    /// Hash password read from stdin, so it can be stored in config of user
+    HashPassword,
+
+    /// Check whether calendar can be parsed and report all problems in it
+    Lint {
+        /// Url or path of calendar
+        source: String,
+    },
+
+    /// Print events of feed from config without starting server
+    Dump {
+        /// Name of feed
+        #[arg(long)]
+        feed: String,
+
+        #[command(flatten)]
+        range: RangeArgs,
+
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+
+    /// Print expanded occurrences of all events from calendar file
+    Expand {
+        /// Path of calendar
+        file: String,
+
+        #[command(flatten)]
+        range: RangeArgs,
+
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RangeArgs {
+    /// Start of range as date or datetime. Today by default
+    #[arg(long)]
+    from: Option<String>,
+
+    /// End of range as date or datetime. 30 days after start by default
+    #[arg(long)]
+    to: Option<String>,
+
+    /// IANA timezone for dates and printed times. UTC by default
+    #[arg(long)]
+    tz: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    Json,
+    Ics,
+}
+
+impl Command {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        match self {
+            Command::Serve => serve().await?,
+            Command::CheckConfig => return check_config(),
+            Command::GenToken { length } => {
+                let token = tokens::generate_token(length);
+                println!("Token:           {}", token);
+                println!("Value in config: {}", tokens::hashed_token(&token));
+            }
+            Command::HashPassword => hash_password()?,
+            Command::Lint { source } => return lint_source(&source).await,
+            Command::Dump {
+                feed,
+                range,
+                format,
+            } => dump(&feed, &range, format).await?,
+            Command::Expand {
+                file,
+                range,
+                format,
+            } => expand(&file, &range, format).await?,
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+impl RangeArgs {
+    /// Returns range in UTC and timezone requested for output
+    fn parse(&self) -> anyhow::Result<(DateTime<Utc>, DateTime<Utc>, Option<Tz>)> {
+        let tz = self.tz.as_deref().map(parse_timezone).transpose()?;
+        let local_tz = tz.unwrap_or(Tz::UTC);
+
+        let from = self
+            .from
+            .clone()
+            .unwrap_or_else(|| Utc::now().with_timezone(&local_tz).date_naive().to_string());
+        let to = match &self.to {
+            Some(to) => to.clone(),
+            None => {
+                let start = parse_datetime(&from, local_tz)
+                    .ok_or_else(|| RangeError::InvalidDatetime("start", from.clone()))?;
+                (start + Duration::days(DEFAULT_RANGE_DAYS)).to_rfc3339()
+            }
+        };
+
+        let (start, end) = parse_range(&from, &to, local_tz)?;
+        Ok((start, end, tz))
+    }
+}
+
+fn parse_token_length(value: &str) -> Result<usize, String> {
+    let length: usize = value.parse().map_err(|_| "must be a number".to_string())?;
+    if length < MIN_TOKEN_LENGTH {
+        return Err(format!("must be at least {}", MIN_TOKEN_LENGTH));
+    }
+    Ok(length)
+}
+
+async fn serve() -> anyhow::Result<()> {
+    tracing::warn!("Warn logging is enabled");
+    tracing::info!("Info logging is enabled");
+    tracing::debug!("Debug logging is enabled");
+    tracing::trace!("Trace logging is enabled");
+
+    let config = AppConfig::load()?;
+
+    let application = Application::build(config).await?;
+    application.wait_finish().await?;
+
+    Ok(())
+}
+
+fn check_config() -> anyhow::Result<ExitCode> {
+    let config = AppConfig::read().context("Failed to load config")?;
+    let report = config.validate();
+    for issue in &report.issues {
+        println!("{:?}: {}", issue.severity, issue);
+    }
+
+    if report.has_errors() {
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let calendars: usize = config.feeds.iter().map(|f| f.calendars.len()).sum();
+    println!(
+        "Config is valid: {} feed(s), {} calendar(s)",
+        config.feeds.len(),
+        calendars
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn hash_password() -> anyhow::Result<()> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read password")?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+
+    println!("{}", users::hash_password(password)?);
+    Ok(())
+}
+
+async fn lint_source(source: &str) -> anyhow::Result<ExitCode> {
+    let report = if source.starts_with("http://") || source.starts_with("https://") {
+        let config = AppConfig::load()?;
+        lint::lint_url(&fetch::client()?, source, &config.fetch).await?
+    } else {
+        let bytes = tokio::fs::read(source).await?;
+        lint::lint_calendar(&bytes)
+    };
+
+    print_report(&report);
+
+    if report.is_ok() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn print_report(report: &LintReport) {
+    println!(
+        "Calendars: {}, events: {}, event sets: {}",
+        report.calendars, report.events, report.event_sets
+    );
+    for issue in &report.issues {
+        match &issue.id {
+            Some(id) => println!("{:?} [{}]: {}", issue.kind, id, issue.message),
+            None => println!("{:?}: {}", issue.kind, issue.message),
+        }
+    }
+    if report.is_ok() {
+        println!("No problems found");
+    } else {
+        println!("Found {} problem(s)", report.issues.len());
+    }
+}
+
+async fn dump(name: &str, range: &RangeArgs, format: Format) -> anyhow::Result<()> {
+    let (start, end, tz) = range.parse()?;
+    let config = AppConfig::load()?;
+    let stored = match FeedStore::from_config(&config) {
+        Some(store) => store.load().await?,
+        None => vec![],
+    };
+    let config = admin::merge_feeds(&config, &stored)?;
+
+    let service = FeedService::new(SharedConfig::new(config))?;
+    service.load_disk_cache().await;
+    let feed = service
+        .get_feed_by_name(name, start, end, tz.unwrap_or(Tz::UTC))
+        .await?;
+    for diagnostics in &feed.diagnostics {
+        tracing::warn!("Calendar is degraded: {:?}", diagnostics);
+    }
+
+    let events = feed.events.into_iter().map(|e| e.event).collect();
+    print_events(name, events, tz, format)
+}
+
+async fn expand(file: &str, range: &RangeArgs, format: Format) -> anyhow::Result<()> {
+    let (start, end, tz) = range.parse()?;
+    let bytes = tokio::fs::read(file)
+        .await
+        .with_context(|| format!("Failed to read {}", file))?;
+
+    let parsed = ParsedCalendar::parse(&bytes, None);
+    let events = parsed.create_primitives(start, end, &tz.unwrap_or(Tz::UTC));
+
+    print_events(file, events, tz, format)
+}
+
+fn print_events(
+    name: &str,
+    mut events: Vec<PrimitiveEvent>,
+    tz: Option<Tz>,
+    format: Format,
+) -> anyhow::Result<()> {
+    events.sort_by_key(|event| event.range.start().into_datetime());
+
+    match format {
+        Format::Json => {
+            let events: Vec<_> = events
+                .into_iter()
+                .map(|event| EventDto::new(event, tz))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&events)?);
+        }
+        Format::Ics => print!("{}", ics::write_calendar(name, &events)),
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
+pub mod cli;
 mod model;
 mod routes;
 mod service;
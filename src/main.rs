@@ -1,9 +1,13 @@
-use icaliada::config::AppConfig;
-use icaliada::Application;
+use std::process::ExitCode;
+
+use clap::Parser;
+use icaliada::cli::{Cli, Command};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+
     // Populate environment from .env
     let dot_env_missing = dotenv::dotenv().is_err();
 
@@ -12,22 +16,13 @@ async fn main() -> anyhow::Result<()> {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "icaliada=info".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
-    if dot_env_missing {
+    let command = cli.command.unwrap_or(Command::Serve);
+    if dot_env_missing && matches!(command, Command::Serve) {
         tracing::warn!(".env file is missing");
     }
 
-    tracing::warn!("Warn logging is enabled");
-    tracing::info!("Info logging is enabled");
-    tracing::debug!("Debug logging is enabled");
-    tracing::trace!("Trace logging is enabled");
-
-    let config = AppConfig::load()?;
-
-    let application = Application::build(config).await?;
-    application.wait_finish().await?;
-
-    Ok(())
+    command.run().await
 }
@@ -2,8 +2,9 @@ use std::collections::HashMap;
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum DatePerhapsTime {
     Date(NaiveDate),
     DateTime(DateTime<Utc>),
@@ -29,7 +30,7 @@ impl DatePerhapsTime {
     pub fn new(
         value: String,
         props: Vec<(String, Vec<String>)>,
-        local_to_utc: impl Fn(&str, NaiveDateTime) -> DateTime<Utc>,
+        local_to_utc: impl Fn(&str, NaiveDateTime) -> Result<DateTime<Utc>>,
     ) -> Result<DatePerhapsTime> {
         let props: HashMap<_, _> = props.into_iter().collect();
 
@@ -50,7 +51,7 @@ impl DatePerhapsTime {
 
 /// In time range both start and end must be the same variant
 /// (either both date or both datetime)
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct TimeRange {
     start: DatePerhapsTime,
     end: DatePerhapsTime,
@@ -69,11 +70,14 @@ impl TimeRange {
         }
     }
 
-    pub fn intersects(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+    /// Checks whether range intersects with given one.
+    /// All day ranges are compared with dates of given range in specified timezone
+    pub fn intersects(&self, start: &DateTime<Utc>, end: &DateTime<Utc>, tz: &Tz) -> bool {
         match (&self.start, &self.end) {
             (DatePerhapsTime::DateTime(s), DatePerhapsTime::DateTime(e)) => end >= s && start <= e,
             (DatePerhapsTime::Date(s), DatePerhapsTime::Date(e)) => {
-                &end.date_naive() >= s && &start.date_naive() <= e
+                &end.with_timezone(tz).date_naive() >= s
+                    && &start.with_timezone(tz).date_naive() <= e
             }
             _ => unreachable!(),
         }
@@ -91,7 +95,7 @@ impl TimeRange {
         start_props: Vec<(String, Vec<String>)>,
         end: String,
         end_props: Vec<(String, Vec<String>)>,
-        local_to_utc: impl Fn(&str, NaiveDateTime) -> DateTime<Utc>,
+        local_to_utc: impl Fn(&str, NaiveDateTime) -> Result<DateTime<Utc>>,
     ) -> Result<Self> {
         let start = DatePerhapsTime::new(start, start_props, &local_to_utc)?;
         let end = DatePerhapsTime::new(end, end_props, &local_to_utc)?;
@@ -128,7 +132,7 @@ impl TimeRange {
 fn convert_datetime(
     value: String,
     properties: HashMap<String, Vec<String>>,
-    local_to_utc: impl Fn(&str, NaiveDateTime) -> DateTime<Utc>,
+    local_to_utc: impl Fn(&str, NaiveDateTime) -> Result<DateTime<Utc>>,
 ) -> Result<DateTime<Utc>> {
     let fmt = "%Y%m%dT%H%M%S";
     if value.ends_with('Z') {
@@ -150,7 +154,7 @@ fn convert_datetime(
         anyhow::ensure!(prop.len() == 1, "TZID must be set only once");
         let timezone = &prop[0];
 
-        Ok(local_to_utc(timezone, time))
+        local_to_utc(timezone, time)
     }
 }
 
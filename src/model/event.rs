@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use crate::model::datetime::{DatePerhapsTime, TimeRange};
 use anyhow::{Context, Result};
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
 use chrono_tz::Tz;
 use ical::parser::ical::component::IcalEvent;
 use rrule::{RRule, RRuleSet};
@@ -20,7 +20,7 @@ pub struct CalendarEvent {
 impl CalendarEvent {
     pub fn from_ical_event(
         value: IcalEvent,
-        local_to_utc: impl Fn(&str, NaiveDateTime) -> DateTime<Utc>,
+        local_to_utc: impl Fn(&str, NaiveDateTime) -> Result<DateTime<Utc>>,
     ) -> Result<Self> {
         // todo proper errors
         let mut props: HashMap<_, _> = value
@@ -94,6 +94,9 @@ impl CalendarEvent {
 pub struct PrimitiveEvent {
     pub range: TimeRange,
     pub summary: String,
+    pub uid: String,
+    /// Start of occurrence before override, identifies it together with uid
+    pub instance: DatePerhapsTime,
 }
 
 #[derive(Clone, Debug)]
@@ -122,13 +125,21 @@ impl EventSet {
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+        tz: &Tz,
     ) -> Vec<PrimitiveEvent> {
         match &self.recurrence {
             Some(rule) => {
-                let start = start.with_timezone(&rrule::Tz::Tz(Tz::UTC));
-                let end = end.with_timezone(&rrule::Tz::Tz(Tz::UTC));
+                let (rule_start, rule_end) = if self.range.is_all_day() {
+                    // all day occurrences start at utc midnight, so range is extended
+                    // to cover whole local days and then filtered by local dates
+                    (start - Duration::days(1), end + Duration::days(1))
+                } else {
+                    (start, end)
+                };
+                let rule_start = rule_start.with_timezone(&rrule::Tz::Tz(Tz::UTC));
+                let rule_end = rule_end.with_timezone(&rrule::Tz::Tz(Tz::UTC));
                 // limited not checked
-                let result = rule.clone().after(start).before(end).all(100);
+                let result = rule.clone().after(rule_start).before(rule_end).all(100);
 
                 if result.limited {
                     tracing::warn!("RRule expansion gave more than 100 results!")
@@ -138,17 +149,23 @@ impl EventSet {
                     .dates
                     .into_iter()
                     .map(|s| s.with_timezone(&Utc))
-                    .map(|start| PrimitiveEvent {
-                        range: self.range.with_start(start),
+                    .map(|start| self.range.with_start(start))
+                    .map(|range| PrimitiveEvent {
+                        instance: range.start(),
+                        range,
                         summary: self.summary.clone(),
+                        uid: self.uid.clone(),
                     })
+                    .filter(|event| event.range.intersects(&start, &end, tz))
                     .collect()
             }
             None => {
-                if self.range.intersects(&start, &end) {
+                if self.range.intersects(&start, &end, tz) {
                     vec![PrimitiveEvent {
                         range: self.range.clone(),
                         summary: self.summary.clone(),
+                        uid: self.uid.clone(),
+                        instance: self.range.start(),
                     }]
                 } else {
                     vec![]
@@ -157,13 +174,15 @@ impl EventSet {
         }
     }
 
-    /// Creates list of primitive events for this event set
+    /// Creates list of primitive events for this event set.
+    /// All day events are selected by dates in given timezone
     pub fn create_primitives(
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+        tz: &Tz,
     ) -> Vec<PrimitiveEvent> {
-        let initial = self.create_initial_events(start, end);
+        let initial = self.create_initial_events(start, end, tz);
 
         initial
             .into_iter()
@@ -177,6 +196,7 @@ impl EventSet {
                     PrimitiveEvent {
                         range: event_override.range.clone(),
                         summary: event_override.summary.clone(),
+                        ..event
                     }
                 } else {
                     event
@@ -1,5 +1,6 @@
+pub use datetime::DatePerhapsTime;
 pub use event::{CalendarEvent, EventSet, PrimitiveEvent};
-pub use timezone::Timezone;
+pub use timezone::{iana_local_to_utc, Timezone};
 
 mod datetime;
 mod event;
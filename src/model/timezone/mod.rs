@@ -1,15 +1,23 @@
 use anyhow::Context;
-use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
 use ical::parser::ical::component::{IcalTimeZone, IcalTimeZoneTransition};
 use rrule::{RRule, RRuleSet, Tz};
 use std::str::FromStr;
+use std::sync::RwLock;
 
 use crate::service::utils;
 
-#[derive(Clone, Debug)]
+/// Transition table is extended this far past requested datetime,
+/// so it is rarely extended again
+const TABLE_EXTENSION_DAYS: i64 = 20 * 365;
+
+#[derive(Debug)]
 pub struct Timezone {
     id: String,
     transitions: Vec<TimezoneTransition>,
+    /// Offset used before first transition
+    initial_offset: FixedOffset,
+    table: RwLock<TransitionTable>,
 }
 
 #[derive(Clone, Debug)]
@@ -19,6 +27,61 @@ pub struct TimezoneTransition {
     to: FixedOffset,
 }
 
+impl TimezoneTransition {
+    /// Length of local time range skipped by this transition (zero when clocks go back)
+    fn gap(&self) -> Duration {
+        let gap = self.to.local_minus_utc() - self.from.local_minus_utc();
+        Duration::seconds(gap.max(0) as i64)
+    }
+}
+
+/// All transitions up to `until` (with offsets after them), sorted by time.
+///
+/// Transition times are local times at which new offset starts to apply:
+/// - transition DTSTART is local time before transition (with `from` offset), so
+///   when clocks go back, repeated local times before DTSTART use `from` offset
+///   and are resolved to their first occurrence
+/// - when clocks go forward, skipped local times also use `from` offset,
+///   so they are shifted forward by length of the gap
+///
+/// These are the rules from RFC 5545 (section 3.3.5)
+#[derive(Debug, Default)]
+struct TransitionTable {
+    until: Option<DateTime<Tz>>,
+    entries: Vec<(DateTime<Tz>, FixedOffset)>,
+}
+
+impl TransitionTable {
+    fn covers(&self, datetime: &DateTime<Tz>) -> bool {
+        self.until.is_some_and(|until| datetime <= &until)
+    }
+
+    /// Returns offset after last transition before or at given datetime
+    fn lookup(&self, datetime: &DateTime<Tz>) -> Option<FixedOffset> {
+        let idx = self.entries.partition_point(|(time, _)| time <= datetime);
+        idx.checked_sub(1).map(|i| self.entries[i].1)
+    }
+
+    fn extend(&mut self, transitions: &[TimezoneTransition], until: DateTime<Tz>) {
+        if self.covers(&until) {
+            return;
+        }
+
+        let from = self.until;
+        for transition in transitions {
+            let rule = transition.rule.clone().limit();
+            let occurrences = rule
+                .into_iter()
+                .skip_while(|d| from.is_some_and(|from| d <= &from))
+                .take_while(|d| d <= &until)
+                .map(|d| (d + transition.gap(), transition.to));
+            self.entries.extend(occurrences);
+        }
+        self.entries.sort_by_key(|(time, _)| *time);
+        self.until = Some(until);
+    }
+}
+
 impl Timezone {
     pub fn id(&self) -> &str {
         &self.id
@@ -28,61 +91,63 @@ impl Timezone {
         //rrule crate works only with timezoned dates, so assume local as utc
         let datetime = Tz::UTC.from_local_datetime(&datetime).unwrap();
 
-        let mut last_transition_time = None;
-        let mut last_transition_offset = None;
+        let offset = self.offset_at(&datetime);
 
-        for transition in &self.transitions {
-            let last = transition
-                .rule
-                .clone()
-                .limit()
-                .into_iter()
-                .take_while(|d| d <= &datetime)
-                .last();
+        let new_date = offset.from_local_datetime(&datetime.naive_utc()).unwrap();
 
-            if let Some(last) = last {
-                if let Some(last_tr) = last_transition_time {
-                    if last > last_tr {
-                        last_transition_time = Some(last);
-                        last_transition_offset = Some(transition.to);
-                    }
-                } else {
-                    last_transition_time = Some(last);
-                    last_transition_offset = Some(transition.to);
-                }
-            }
-        }
+        new_date.with_timezone(&Utc)
+    }
 
-        let offset = if let Some(offset) = last_transition_offset {
-            offset
-        } else {
-            // date is before all transitions, so find first transition and take its offset_from
-            //todo precompute
-            let mut first_transition_time = None;
-            let mut first_transition_offset = None;
-
-            for transition in &self.transitions {
-                let first = transition.rule.clone().into_iter().next();
-
-                if let Some(first) = first {
-                    if let Some(first_tr) = first_transition_time {
-                        if first < first_tr {
-                            first_transition_time = Some(first);
-                            first_transition_offset = Some(transition.from);
-                        }
-                    } else {
-                        first_transition_time = Some(first);
-                        first_transition_offset = Some(transition.from);
-                    }
-                }
+    fn offset_at(&self, datetime: &DateTime<Tz>) -> FixedOffset {
+        {
+            let table = self.table.read().unwrap();
+            if table.covers(datetime) {
+                return table.lookup(datetime).unwrap_or(self.initial_offset);
             }
+        }
 
-            first_transition_offset.unwrap()
-        };
+        let mut table = self.table.write().unwrap();
+        let until = datetime
+            .checked_add_signed(Duration::days(TABLE_EXTENSION_DAYS))
+            .unwrap_or(*datetime);
+        table.extend(&self.transitions, until);
+        table.lookup(datetime).unwrap_or(self.initial_offset)
+    }
 
-        let new_date = offset.from_local_datetime(&datetime.naive_utc()).unwrap();
+    /// Transitions must not be empty
+    fn new(id: String, transitions: Vec<TimezoneTransition>) -> Self {
+        // date before all transitions has offset_from of first transition
+        let initial_offset = transitions
+            .iter()
+            .filter_map(|transition| {
+                let first = transition.rule.clone().into_iter().next()?;
+                Some((first, transition.from))
+            })
+            .min_by_key(|(first, _)| *first)
+            .map_or(transitions[0].from, |(_, offset)| offset);
+
+        Self {
+            id,
+            transitions,
+            initial_offset,
+            table: Default::default(),
+        }
+    }
+}
 
-        new_date.with_timezone(&Utc)
+/// Converts local datetime in IANA timezone to UTC using the same rules as [`Timezone`]:
+/// skipped local times are shifted forward and repeated local times take first occurrence
+pub fn iana_local_to_utc(tz: chrono_tz::Tz, datetime: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&datetime) {
+        LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => date.with_timezone(&Utc),
+        LocalResult::None => {
+            // use offset from before the gap
+            let before = tz
+                .offset_from_utc_datetime(&(datetime - Duration::days(1)))
+                .fix();
+            let utc = datetime - Duration::seconds(before.local_minus_utc() as i64);
+            Utc.from_utc_datetime(&utc)
+        }
     }
 }
 
@@ -98,67 +163,71 @@ impl TryFrom<IcalTimeZone> for Timezone {
             .map(utils::unescape)
             .context("Timezone ID is missing")?;
 
-        let mut transitions = vec![];
-
-        for cal_trans in cal_tz.transitions {
-            transitions.push(parse_transition(cal_trans));
-        }
-
-        let timezone = Timezone { id, transitions };
+        anyhow::ensure!(
+            !cal_tz.transitions.is_empty(),
+            "Timezone {} has no transitions",
+            id
+        );
 
-        Ok(timezone)
+        let transitions = cal_tz
+            .transitions
+            .into_iter()
+            .enumerate()
+            .map(|(i, cal_trans)| {
+                parse_transition(cal_trans)
+                    .with_context(|| format!("Failed to parse transition {} of {}", i, id))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Timezone::new(id, transitions))
     }
 }
 
-fn parse_transition(cal_transition: IcalTimeZoneTransition) -> TimezoneTransition {
+fn parse_transition(cal_transition: IcalTimeZoneTransition) -> anyhow::Result<TimezoneTransition> {
     let mut from = None;
     let mut to = None;
 
     let mut dtstart = None;
-    let mut occurences = vec![];
-    let fmt = "%Y%m%dT%H%M%S";
+    let mut rdates = vec![];
 
     let mut rrule = None;
 
     for props in cal_transition.properties {
-        match props.name.as_str() {
-            "TZOFFSETFROM" => {
-                let offset: Option<FixedOffset> = props.value.unwrap().parse().ok();
-                from = offset;
-            }
-            "TZOFFSETTO" => {
-                let offset: Option<FixedOffset> = props.value.unwrap().parse().ok();
-                to = offset;
-            }
+        // other properties (e.g. TZNAME or COMMENT) are ignored, so they may be empty
+        let name = props.name.as_str();
+        let value = || {
+            props
+                .value
+                .as_deref()
+                .with_context(|| format!("{} has no value", name))
+        };
+
+        match name {
+            "TZOFFSETFROM" => from = Some(parse_offset(value()?)?),
+            "TZOFFSETTO" => to = Some(parse_offset(value()?)?),
             "RRULE" => {
-                rrule = Some(RRule::from_str(&props.value.unwrap()).unwrap());
-            }
-            "DTSTART" => {
-                let occurence = props
-                    .value
-                    .and_then(|t| NaiveDateTime::parse_from_str(&t, fmt).ok())
-                    .unwrap();
-                dtstart = Some(occurence);
-                occurences.push(occurence);
+                let value = value()?;
+                let parsed =
+                    RRule::from_str(value).with_context(|| format!("Invalid RRULE: {}", value))?;
+                rrule = Some(parsed);
             }
+            "DTSTART" => dtstart = Some(value()?.to_string()),
             "RDATE" => {
-                let occurence = props
-                    .value
-                    .and_then(|t| NaiveDateTime::parse_from_str(&t, fmt).ok())
-                    .unwrap();
-                occurences.push(occurence);
+                // RDATE may contain several comma separated dates or periods
+                for rdate in value()?.split(',') {
+                    // only start of period is needed
+                    let start = rdate.split('/').next().unwrap_or_default();
+                    rdates.push(start.to_string());
+                }
             }
             _ => {}
         }
     }
 
-    let from = from.unwrap();
-    let to = to.unwrap();
+    let from = from.context("TZOFFSETFROM is missing")?;
+    let to = to.context("TZOFFSETTO is missing")?;
 
-    let dtsart = dtstart.unwrap();
-    // we store all local datetimes with UTC timezone (which is actually incorrect)
-    // but that's the only option with rrule since it doesn't support arbitrary timezones
-    let dtstart = Tz::UTC.from_local_datetime(&dtsart).unwrap();
+    let dtstart = parse_local_datetime(&dtstart.context("DTSTART is missing")?, from)?;
 
     let mut rule = RRuleSet::new(dtstart);
 
@@ -166,30 +235,91 @@ fn parse_transition(cal_transition: IcalTimeZoneTransition) -> TimezoneTransitio
         if let Some(until) = rrule.get_until() {
             let until = Tz::UTC
                 .from_local_datetime(&until.with_timezone(&from).naive_local())
-                .unwrap();
+                .single()
+                .context("Invalid RRULE until")?;
 
             rrule = rrule.until(until);
         }
 
-        let rrule = rrule.validate(dtstart).unwrap();
+        let rrule = rrule
+            .validate(dtstart)
+            .context("Failed to validate RRULE")?;
 
         rule = rule.rrule(rrule);
-    } else {
-        let occurences = occurences
-            .into_iter()
-            .map(|o| Tz::UTC.from_local_datetime(&o).unwrap())
-            .collect();
+    }
+
+    let rdates = rdates
+        .iter()
+        .map(|rdate| parse_local_datetime(rdate, from))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-        rule = rule.set_rdates(occurences);
+    if rule.get_rrule().is_empty() {
+        // without RRULE, DTSTART is the first occurrence
+        rule = rule.set_rdates(std::iter::once(dtstart).chain(rdates).collect());
+    } else {
+        rule = rule.set_rdates(rdates.into_iter().filter(|d| d != &dtstart).collect());
     }
 
-    TimezoneTransition { rule, from, to }
+    Ok(TimezoneTransition { rule, from, to })
+}
+
+/// Parses local datetime of transition.
+/// UTC datetimes are converted to local time before transition using `from` offset.
+///
+/// We store all local datetimes with UTC timezone (which is actually incorrect)
+/// but that's the only option with rrule since it doesn't support arbitrary timezones
+fn parse_local_datetime(value: &str, from: FixedOffset) -> anyhow::Result<DateTime<Tz>> {
+    let fmt = "%Y%m%dT%H%M%S";
+
+    let local = if let Some(value) = value.strip_suffix('Z') {
+        let utc = NaiveDateTime::parse_from_str(value, fmt)
+            .with_context(|| format!("Invalid datetime: {}", value))?;
+        utc + Duration::seconds(from.local_minus_utc() as i64)
+    } else {
+        NaiveDateTime::parse_from_str(value, fmt)
+            .with_context(|| format!("Invalid datetime: {}", value))?
+    };
+
+    Tz::UTC
+        .from_local_datetime(&local)
+        .single()
+        .context("Invalid datetime")
+}
+
+/// Parses UTC offset in `+HHMM` or `+HHMMSS` format
+fn parse_offset(value: &str) -> anyhow::Result<FixedOffset> {
+    let invalid = || format!("Invalid UTC offset: {}", value);
+
+    let (sign, digits) = if let Some(digits) = value.strip_prefix('+') {
+        (1, digits)
+    } else if let Some(digits) = value.strip_prefix('-') {
+        (-1, digits)
+    } else {
+        anyhow::bail!(invalid())
+    };
+    anyhow::ensure!(
+        (digits.len() == 4 || digits.len() == 6) && digits.chars().all(|c| c.is_ascii_digit()),
+        invalid()
+    );
+
+    let part = |i: usize| {
+        digits
+            .get(i..i + 2)
+            .map_or(0, |p| p.parse::<i32>().unwrap())
+    };
+    let seconds = part(0) * 3600 + part(2) * 60 + part(4);
+
+    FixedOffset::east_opt(sign * seconds).with_context(invalid)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::model::Timezone;
-    use chrono::{DateTime, NaiveDateTime, Utc};
+    use std::time::Instant;
+
+    use crate::model::timezone::parse_offset;
+    use crate::model::{iana_local_to_utc, Timezone};
+    use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, TimeZone, Utc};
+    use rrule::Tz;
     use rstest::rstest;
 
     #[rstest]
@@ -204,6 +334,15 @@ mod tests {
     #[case("2010-03-15T00:00:00", "2010-03-15T04:00:00Z")]
     #[case("2010-11-06T00:00:00", "2010-11-06T04:00:00Z")]
     #[case("2010-11-08T00:00:00", "2010-11-08T05:00:00Z")]
+    // skipped hour is shifted forward
+    #[case("2010-03-14T01:59:00", "2010-03-14T06:59:00Z")]
+    #[case("2010-03-14T02:00:00", "2010-03-14T07:00:00Z")]
+    #[case("2010-03-14T02:30:00", "2010-03-14T07:30:00Z")]
+    #[case("2010-03-14T03:00:00", "2010-03-14T07:00:00Z")]
+    // repeated hour takes first occurrence
+    #[case("2010-11-07T00:59:00", "2010-11-07T04:59:00Z")]
+    #[case("2010-11-07T01:30:00", "2010-11-07T05:30:00Z")]
+    #[case("2010-11-07T02:00:00", "2010-11-07T07:00:00Z")]
     fn test_new_york(#[case] addr: &str, #[case] expected: &str) {
         let bytes = include_bytes!("test-tz-new-york.ics");
         test_date_conversion(bytes, addr, expected);
@@ -213,22 +352,218 @@ mod tests {
     #[case("2010-10-30T02:00:00", "2010-10-29T22:00:00Z")]
     #[case("2010-11-01T02:00:00", "2010-10-31T23:00:00Z")]
     #[case("2015-11-01T02:00:00", "2015-10-31T23:00:00Z")]
+    // skipped hour is shifted forward
+    #[case("2010-03-28T01:59:00", "2010-03-27T22:59:00Z")]
+    #[case("2010-03-28T02:30:00", "2010-03-27T23:30:00Z")]
+    #[case("2010-03-28T03:30:00", "2010-03-27T23:30:00Z")]
+    // repeated hour takes first occurrence
+    #[case("2010-10-31T02:30:00", "2010-10-30T22:30:00Z")]
+    #[case("2010-10-31T03:00:00", "2010-10-31T00:00:00Z")]
+    #[case("2014-10-26T01:30:00", "2014-10-25T21:30:00Z")]
+    #[case("2014-10-26T02:00:00", "2014-10-25T23:00:00Z")]
     fn test_moscow(#[case] addr: &str, #[case] expected: &str) {
         let bytes = include_bytes!("test-tz-moscow.ics");
         test_date_conversion(bytes, addr, expected);
     }
 
-    fn test_date_conversion(ical_bytes: &[u8], local_date: &str, expected_date: &str) {
-        let local_date = NaiveDateTime::parse_from_str(local_date, "%Y-%m-%dT%H:%M:%S").unwrap();
-        let expected_date = DateTime::parse_from_rfc3339(expected_date)
+    #[rstest]
+    #[case("+0300", 3 * 3600)]
+    #[case("-0430", -(4 * 3600 + 30 * 60))]
+    #[case("+023017", 2 * 3600 + 30 * 60 + 17)]
+    fn test_parse_offset(#[case] value: &str, #[case] expected: i32) {
+        assert_eq!(
+            parse_offset(value).unwrap(),
+            FixedOffset::east_opt(expected).unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case("0300")]
+    #[case("+03")]
+    #[case("+03:00")]
+    fn test_parse_invalid_offset(#[case] value: &str) {
+        assert!(parse_offset(value).is_err());
+    }
+
+    #[rstest]
+    // multiple RDATE values on single line
+    #[case(
+        "RDATE:20000101T000000,20000601T000000",
+        "2000-07-01T00:00:00",
+        "2000-06-30T22:00:00Z"
+    )]
+    // RDATE period values
+    #[case(
+        "RDATE;VALUE=PERIOD:20000601T000000/20000602T000000",
+        "2000-07-01T00:00:00",
+        "2000-06-30T22:00:00Z"
+    )]
+    // DTSTART in UTC
+    #[case("", "1999-12-31T23:30:00", "1999-12-31T22:30:00Z")]
+    // empty properties which are not used
+    #[case(
+        "TZNAME:\r\nCOMMENT:\r\nX-LIC-LOCATION:",
+        "1999-12-31T23:30:00",
+        "1999-12-31T22:30:00Z"
+    )]
+    fn test_transition_formats(#[case] rdate: &str, #[case] local: &str, #[case] expected: &str) {
+        let ical = format!(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VTIMEZONE\r\n\
+             TZID:Test\r\n\
+             BEGIN:STANDARD\r\n\
+             DTSTART:19991231T220000Z\r\n\
+             {}\r\n\
+             TZOFFSETFROM:+0100\r\n\
+             TZOFFSETTO:+0200\r\n\
+             END:STANDARD\r\n\
+             END:VTIMEZONE\r\n\
+             END:VCALENDAR\r\n",
+            rdate
+        );
+
+        test_date_conversion(ical.as_bytes(), local, expected);
+    }
+
+    #[rstest]
+    #[case("TZOFFSETTO:+0200\r\nDTSTART:20000101T000000")]
+    #[case("TZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200")]
+    #[case("TZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nDTSTART:20000101T000000\r\nRRULE:FREQ=NEVER")]
+    #[case("TZOFFSETFROM:+0100\r\nTZOFFSETTO:\r\nDTSTART:20000101T000000")]
+    fn test_broken_transition(#[case] props: &str) {
+        let ical = format!(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VTIMEZONE\r\n\
+             TZID:Test\r\n\
+             BEGIN:STANDARD\r\n\
+             {}\r\n\
+             END:STANDARD\r\n\
+             END:VTIMEZONE\r\n\
+             END:VCALENDAR\r\n",
+            props
+        );
+
+        let reader = ical::IcalParser::new(ical.as_bytes());
+        let calendar = reader.flatten().next().unwrap();
+        let cal_tz = calendar.timezones.into_iter().next().unwrap();
+
+        assert!(Timezone::try_from(cal_tz).is_err());
+    }
+
+    #[rstest]
+    #[case("2010-03-14T02:30:00", "2010-03-14T07:30:00Z")]
+    #[case("2010-11-07T01:30:00", "2010-11-07T05:30:00Z")]
+    #[case("2010-11-08T00:00:00", "2010-11-08T05:00:00Z")]
+    fn test_iana_fallback(#[case] local: &str, #[case] expected: &str) {
+        let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M:%S").unwrap();
+        let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+
+        assert_eq!(
+            iana_local_to_utc(chrono_tz::America::New_York, local),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(include_bytes!("test-tz-new-york.ics"))]
+    #[case(include_bytes!("test-tz-moscow.ics"))]
+    fn test_table_matches_linear_scan(#[case] bytes: &[u8]) {
+        let timezone = parse_timezone(bytes);
+
+        for date in test_dates() {
+            assert_eq!(
+                timezone.local_to_utc(date),
+                linear_local_to_utc(&timezone, date),
+                "{}",
+                date
+            );
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_local_to_utc --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_local_to_utc() {
+        let timezone = parse_timezone(include_bytes!("test-tz-new-york.ics"));
+        let dates = test_dates();
+
+        let started = Instant::now();
+        for &date in &dates {
+            std::hint::black_box(linear_local_to_utc(&timezone, date));
+        }
+        let linear = started.elapsed();
+
+        let started = Instant::now();
+        for &date in &dates {
+            std::hint::black_box(timezone.local_to_utc(date));
+        }
+        let table = started.elapsed();
+
+        println!(
+            "{} conversions: linear scan {:?}, transition table {:?}",
+            dates.len(),
+            linear,
+            table
+        );
+    }
+
+    /// Every 13 days (at varying hours) from 1960 to 2040
+    fn test_dates() -> Vec<NaiveDateTime> {
+        let start =
+            NaiveDateTime::parse_from_str("1960-01-01T00:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        (0..80 * 28)
+            .map(|i| start + Duration::days(i * 13) + Duration::hours(i % 24))
+            .collect()
+    }
+
+    /// Previous implementation which scans all transitions on each conversion
+    fn linear_local_to_utc(timezone: &Timezone, datetime: NaiveDateTime) -> DateTime<Utc> {
+        let datetime = Tz::UTC.from_local_datetime(&datetime).unwrap();
+
+        let mut last_transition = None;
+        for transition in &timezone.transitions {
+            let last = transition
+                .rule
+                .clone()
+                .limit()
+                .into_iter()
+                .map(|d| d + transition.gap())
+                .take_while(|d| d <= &datetime)
+                .last();
+
+            match (last, last_transition) {
+                (Some(last), Some((time, _))) if last <= time => {}
+                (Some(last), _) => last_transition = Some((last, transition.to)),
+                _ => {}
+            }
+        }
+
+        let offset = match last_transition {
+            Some((_, offset)) => offset,
+            None => timezone.initial_offset,
+        };
+
+        offset
+            .from_local_datetime(&datetime.naive_utc())
             .unwrap()
-            .with_timezone(&Utc);
+            .with_timezone(&Utc)
+    }
 
+    fn parse_timezone(ical_bytes: &[u8]) -> Timezone {
         let reader = ical::IcalParser::new(ical_bytes);
         let calendar = reader.flatten().next().unwrap();
         let cal_tz = calendar.timezones.into_iter().next().unwrap();
 
-        let timezone = Timezone::try_from(cal_tz).unwrap();
+        Timezone::try_from(cal_tz).unwrap()
+    }
+
+    fn test_date_conversion(ical_bytes: &[u8], local_date: &str, expected_date: &str) {
+        let local_date = NaiveDateTime::parse_from_str(local_date, "%Y-%m-%dT%H:%M:%S").unwrap();
+        let expected_date = DateTime::parse_from_rfc3339(expected_date)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let timezone = parse_timezone(ical_bytes);
         let date = timezone.local_to_utc(local_date);
 
         assert_eq!(date, expected_date);
@@ -0,0 +1,140 @@
+use askama_axum::IntoResponse;
+use axum::body::Bytes;
+use axum::extract::{Path, Query};
+use axum::Extension;
+use hyper::StatusCode;
+use serde::Deserialize;
+
+use crate::config::{CalendarConfig, SharedConfig};
+use crate::routes::auth::AdminAuth;
+use crate::routes::error_response::{ApiError, ApiResult};
+use crate::service::admin::{Actor, AdminService, FeedUpdate, NewFeed, NewToken};
+use crate::service::feeds::FeedService;
+use crate::service::lint;
+
+pub async fn get_calendar_statuses(
+    _: AdminAuth,
+    Extension(feed): Extension<FeedService>,
+) -> ApiResult<impl IntoResponse> {
+    Ok(axum::Json(feed.calendar_statuses()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LintQuery {
+    /// Url of calendar to check. Request body is checked when not set
+    url: Option<String>,
+}
+
+/// Checks calendar and reports all problems that make its events skipped or incorrect
+pub async fn lint_calendar(
+    _: AdminAuth,
+    Query(params): Query<LintQuery>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(feed_service): Extension<FeedService>,
+    body: Bytes,
+) -> ApiResult<impl IntoResponse> {
+    let config = config.get();
+
+    let report = match params.url {
+        Some(url) => lint::lint_url(feed_service.client(), &url, &config.fetch)
+            .await
+            .map_err(|err| ApiError::BadRequest(format!("Failed to download calendar: {}", err)))?,
+        None if body.is_empty() => {
+            return Err(ApiError::BadRequest(
+                "Calendar url or body must be present".to_string(),
+            ))
+        }
+        None => tokio::task::spawn_blocking(move || lint::lint_calendar(&body))
+            .await
+            .map_err(anyhow::Error::from)?,
+    };
+
+    Ok(axum::Json(report))
+}
+
+pub async fn list_feeds(
+    _: AdminAuth,
+    Extension(admin): Extension<AdminService>,
+) -> ApiResult<impl IntoResponse> {
+    Ok(axum::Json(admin.list_feeds(Actor::Admin).await))
+}
+
+/// Creates feed and returns its tokens. Tokens are shown only once
+pub async fn create_feed(
+    _: AdminAuth,
+    Extension(admin): Extension<AdminService>,
+    axum::Json(feed): axum::Json<NewFeed>,
+) -> ApiResult<impl IntoResponse> {
+    let tokens = admin.create_feed(Actor::Admin, feed).await?;
+    Ok((StatusCode::CREATED, axum::Json(tokens)))
+}
+
+pub async fn update_feed(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+    axum::Json(update): axum::Json<FeedUpdate>,
+) -> ApiResult<impl IntoResponse> {
+    admin.update_feed(Actor::Admin, &name, update).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_feed(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+) -> ApiResult<impl IntoResponse> {
+    admin.delete_feed(Actor::Admin, &name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn add_calendar(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+    axum::Json(calendar): axum::Json<CalendarConfig>,
+) -> ApiResult<impl IntoResponse> {
+    let index = admin.add_calendar(Actor::Admin, &name, calendar).await?;
+    Ok((
+        StatusCode::CREATED,
+        axum::Json(serde_json::json!({ "index": index })),
+    ))
+}
+
+pub async fn remove_calendar(
+    _: AdminAuth,
+    Path((name, index)): Path<(String, usize)>,
+    Extension(admin): Extension<AdminService>,
+) -> ApiResult<impl IntoResponse> {
+    admin.remove_calendar(Actor::Admin, &name, index).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn add_token(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+    axum::Json(token): axum::Json<NewToken>,
+) -> ApiResult<impl IntoResponse> {
+    let token = admin.add_token(Actor::Admin, &name, token).await?;
+    Ok((StatusCode::CREATED, axum::Json(token)))
+}
+
+pub async fn rotate_token(
+    _: AdminAuth,
+    Path((name, label)): Path<(String, String)>,
+    Extension(admin): Extension<AdminService>,
+) -> ApiResult<impl IntoResponse> {
+    Ok(axum::Json(
+        admin.rotate_token(Actor::Admin, &name, &label).await?,
+    ))
+}
+
+pub async fn revoke_token(
+    _: AdminAuth,
+    Path((name, label)): Path<(String, String)>,
+    Extension(admin): Extension<AdminService>,
+) -> ApiResult<impl IntoResponse> {
+    admin.revoke_token(Actor::Admin, &name, &label).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
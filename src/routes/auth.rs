@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path, Query};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::response::Redirect;
+use axum::Extension;
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+
+use crate::config::{FeedAccess, SharedConfig};
+use crate::routes::error_response::ApiError;
+use crate::service::admin::Actor;
+use crate::service::users::UserService;
+
+/// Tokens given in request. Each token may be passed as:
+/// - path segment: `/feeds/{token}/...`
+/// - header: `Authorization: Bearer {token}` or `Authorization: Bearer {token1},{token2}`
+/// - query parameter: `?token={token}` or `?tokens={token1},{token2}`
+#[derive(Debug)]
+pub struct RequestTokens(pub Vec<String>);
+
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+    tokens: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestTokens {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // route may have no path parameters at all
+        let path = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(mut params)| params.remove("token"));
+        if let Some(token) = path {
+            return Ok(Self(vec![token]));
+        }
+
+        let bearer = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|tokens| tokens.split(',').map(|s| s.trim().to_string()).collect());
+        if let Some(tokens) = bearer {
+            return Ok(Self(tokens));
+        }
+
+        let query = Query::<TokenQuery>::try_from_uri(&parts.uri)
+            .map_err(|err| ApiError::BadRequest(err.body_text()))?
+            .0;
+        let tokens = match (query.tokens, query.token) {
+            (Some(tokens), _) => tokens.split(',').map(|s| s.to_string()).collect(),
+            (None, Some(token)) => vec![token],
+            (None, None) => vec![],
+        };
+        if tokens.is_empty() {
+            return Err(ApiError::BadRequest("Token not present".to_string()));
+        }
+        Ok(Self(tokens))
+    }
+}
+
+/// Feed resolved from single token of request
+#[derive(Debug)]
+pub struct AuthorizedFeed(pub FeedAccess);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthorizedFeed {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequestTokens(tokens) = RequestTokens::from_request_parts(parts, state).await?;
+        let [token] = tokens.as_slice() else {
+            return Err(ApiError::BadRequest("Expected single token".to_string()));
+        };
+
+        let config = shared_config(parts, state).await?;
+        FeedAccess::resolve(config.get(), token)
+            .map(Self)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+/// Request authorized with admin token
+#[derive(Debug)]
+pub struct AdminAuth;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminAuth {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequestTokens(tokens) = RequestTokens::from_request_parts(parts, state).await?;
+        let config = shared_config(parts, state).await?.get();
+
+        match tokens.as_slice() {
+            [token] if config.is_admin_token(token) => Ok(Self),
+            _ => Err(ApiError::Unauthorized),
+        }
+    }
+}
+
+/// Cookie with id of admin session, set after login to admin pages
+pub const ADMIN_COOKIE: &str = "admin_session";
+
+/// Cookie with id of user session, set after login to account pages
+pub const SESSION_COOKIE: &str = "session";
+
+/// Admin logged in to admin pages with admin cookie.
+/// Browser is redirected to admin login page when session is missing
+#[derive(Debug)]
+pub struct AdminSession;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminSession {
+    type Rejection = Redirect;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let login = Redirect::to("/admin/login");
+        let users = user_service(parts, state)
+            .await
+            .map_err(|_| login.clone())?;
+        let jar = CookieJar::from_headers(&parts.headers);
+        let session = jar.get(ADMIN_COOKIE).ok_or_else(|| login.clone())?;
+        match users.is_admin(session.value()).await {
+            true => Ok(Self),
+            false => Err(login),
+        }
+    }
+}
+
+/// User logged in to account pages with session cookie.
+/// Browser is redirected to login page when session is missing
+#[derive(Debug)]
+pub struct UserSession(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for UserSession {
+    type Rejection = Redirect;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let login = Redirect::to("/login");
+        let users = user_service(parts, state)
+            .await
+            .map_err(|_| login.clone())?;
+        let jar = CookieJar::from_headers(&parts.headers);
+        let session = jar.get(SESSION_COOKIE).ok_or_else(|| login.clone())?;
+        users.user(session.value()).await.map(Self).ok_or(login)
+    }
+}
+
+/// Extractor of session for feed management pages, which are the same for admin and users
+pub trait PageAuth:
+    FromRequestParts<(), Rejection = Redirect> + Into<PageSession> + Send + 'static
+{
+}
+
+impl<T> PageAuth for T where
+    T: FromRequestParts<(), Rejection = Redirect> + Into<PageSession> + Send + 'static
+{
+}
+
+/// Session of feed management pages
+#[derive(Debug)]
+pub enum PageSession {
+    Admin,
+    User(String),
+}
+
+impl From<AdminSession> for PageSession {
+    fn from(_: AdminSession) -> Self {
+        Self::Admin
+    }
+}
+
+impl From<UserSession> for PageSession {
+    fn from(UserSession(name): UserSession) -> Self {
+        Self::User(name)
+    }
+}
+
+impl PageSession {
+    pub fn actor(&self) -> Actor<'_> {
+        match self {
+            PageSession::Admin => Actor::Admin,
+            PageSession::User(name) => Actor::User(name),
+        }
+    }
+
+    /// Path of feeds page, other pages are under it
+    pub fn base(&self) -> &'static str {
+        match self {
+            PageSession::Admin => "/admin/ui",
+            PageSession::User(_) => "/account",
+        }
+    }
+}
+
+async fn shared_config<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+) -> Result<SharedConfig, ApiError> {
+    let Extension(config) = Extension::<SharedConfig>::from_request_parts(parts, state)
+        .await
+        .map_err(|err| anyhow::anyhow!("Config is not available: {}", err))?;
+    Ok(config)
+}
+
+async fn user_service<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+) -> Result<UserService, ApiError> {
+    let Extension(users) = Extension::<UserService>::from_request_parts(parts, state)
+        .await
+        .map_err(|err| anyhow::anyhow!("User service is not available: {}", err))?;
+    Ok(users)
+}
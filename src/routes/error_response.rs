@@ -1,22 +1,87 @@
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+
 use askama_axum::IntoResponse;
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
 use hyper::StatusCode;
 use serde_json::json;
 
+use crate::service::admin::AdminError;
+use crate::service::feeds::FeedError;
+use crate::service::range::RangeError;
+
 pub type ApiResult<T> = Result<T, ApiError>;
 
-//todo custom debug to show cause chain
-#[derive(Debug, thiserror::Error)]
+#[derive(thiserror::Error)]
 pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error(transparent)]
+    InvalidQuery(#[from] RangeError),
+
+    #[error("Invalid token")]
+    Unauthorized,
+
     #[error("{0}")]
     NotFound(String),
 
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Calendars are unavailable")]
+    UpstreamUnavailable(#[source] anyhow::Error),
+
     #[error(transparent)]
     Unexpected(#[from] anyhow::Error),
 }
 
+impl From<FeedError> for ApiError {
+    fn from(err: FeedError) -> Self {
+        match err {
+            FeedError::Unavailable(err) => ApiError::UpstreamUnavailable(err),
+            FeedError::NotFound(name) => ApiError::NotFound(format!("Feed '{}' not found", name)),
+        }
+    }
+}
+
+impl From<AdminError> for ApiError {
+    fn from(err: AdminError) -> Self {
+        match err {
+            AdminError::FeedNotFound(_)
+            | AdminError::CalendarNotFound(_)
+            | AdminError::TokenNotFound(_) => ApiError::NotFound(err.to_string()),
+            AdminError::ReadOnly(_) | AdminError::NoStore | AdminError::Conflict => {
+                ApiError::Conflict(err.to_string())
+            }
+            AdminError::Invalid(_) => ApiError::BadRequest(err.to_string()),
+            AdminError::Store(err) => ApiError::Unexpected(err),
+        }
+    }
+}
+
+/// Shows full cause chain, so it is visible in logs
+impl Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)?;
+
+        let mut source = self.source();
+        while let Some(err) = source {
+            write!(f, "\nCaused by: {}", err)?;
+            source = err.source();
+        }
+        Ok(())
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> askama_axum::Response {
-        tracing::error!("{:?}", self);
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!("{:?}", self);
+        } else {
+            tracing::info!("{:?}", self);
+        }
 
         let message = self.to_message();
         let body = json!({
@@ -24,23 +89,34 @@ impl IntoResponse for ApiError {
             "error": message,
         });
         let mut res = axum::Json(body).into_response();
-        *res.status_mut() = self.status_code();
+        *res.status_mut() = status;
+        if status == StatusCode::UNAUTHORIZED {
+            // tells client which scheme of Authorization header is expected
+            res.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
         res
     }
 }
 
 impl ApiError {
-    fn status_code(&self) -> StatusCode {
+    pub(crate) fn status_code(&self) -> StatusCode {
         match self {
+            ApiError::BadRequest(_) | ApiError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
             ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             ApiError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
-    fn to_message(&self) -> String {
+    /// Message shown to client. Causes of server errors may contain
+    /// secrets (e.g. calendar urls), so they are only logged
+    pub(crate) fn to_message(&self) -> String {
         match self {
-            ApiError::NotFound(err) => err.clone(),
-            ApiError::Unexpected(err) => err.to_string(),
+            ApiError::Unexpected(_) => "Internal server error".to_string(),
+            err => err.to_string(),
         }
     }
 }
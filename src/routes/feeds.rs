@@ -1,115 +1,437 @@
-use anyhow::Context;
 use askama::Template;
-use askama_axum::IntoResponse;
+use askama_axum::{IntoResponse, Response};
 use axum::extract::Query;
 use axum::Extension;
-use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use hyper::header::CONTENT_TYPE;
+use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::config::AppConfig;
+use crate::config::{FeedAccess, SharedConfig};
+use crate::model::PrimitiveEvent;
+use crate::routes::auth::{AuthorizedFeed, RequestTokens};
 use crate::routes::error_response::{ApiError, ApiResult};
-use crate::service::feeds::FeedService;
+use crate::service::feeds::{CalendarDiagnostics, EventSource, FeedError, FeedService};
+use crate::service::ics;
+use crate::service::json::EventDto;
+use crate::service::range::{parse_range, parse_timezone};
+
+//TODO: move to config
+/// Colors of feeds shown together, assigned in order of tokens
+const FEED_COLORS: [&str; 5] = ["#E3826F", "#E4A9A4", "#EFBA97", "#F1CCBB", "#E7D5C7"];
 
 #[derive(Template)]
 #[template(path = "feed.html")]
 pub struct FeedTemplate {
     pub title: String,
     pub tokens: Vec<String>,
-    pub colors: Vec<String>,
+    /// Timezone in which calendar is displayed (either IANA name or "local")
+    pub timezone: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct QueryParams {
-    token: Option<String>,
-    tokens: Option<String>,
+    /// IANA name of timezone in which calendar is displayed.
+    /// Browser timezone is used when not set
+    tz: Option<String>,
 }
 
 pub async fn get_html_feed(
+    RequestTokens(tokens): RequestTokens,
     Query(params): Query<QueryParams>,
-    Extension(config): Extension<AppConfig>,
+    Extension(config): Extension<SharedConfig>,
 ) -> ApiResult<impl IntoResponse> {
-    if params.token.is_none() && params.tokens.is_none() {
-        return Err(ApiError::NotFound("Token not present".to_string()));
-    }
-
-    let tokens = params
-        .tokens
-        .map(|tokens| tokens.split(',').map(|s| s.to_string()).collect::<Vec<_>>())
-        .or(params.token.map(|token| vec![token]))
-        .ok_or(ApiError::NotFound("Token not present".to_string()))?;
+    let timezone = match params.tz {
+        Some(tz) => parse_timezone(&tz)?.name().to_string(),
+        None => "local".to_string(),
+    };
 
+    let config = config.get();
     let feeds = tokens
         .iter()
-        .map(|t| config.get_feed_by_token(t))
+        .map(|t| FeedAccess::resolve(config.clone(), t))
         .collect::<Option<Vec<_>>>()
-        .ok_or(ApiError::NotFound("Invalid token".to_string()))?;
-
-    //TODO: move to config
-    let colors = vec![
-        "#E3826F".to_string(),
-        "#E4A9A4".to_string(),
-        "#EFBA97".to_string(),
-        "#F1CCBB".to_string(),
-        "#E7D5C7".to_string(),
-    ];
+        .ok_or(ApiError::Unauthorized)?;
+
     let title = feeds
         .iter()
-        .map(|f| f.name.to_string())
+        .map(|f| f.feed().name.to_string())
         .collect::<Vec<_>>()
         .join(", ");
 
     Ok(FeedTemplate {
         title,
         tokens,
-        colors,
+        timezone,
     })
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EventsQuery {
-    token: String,
     start: String,
     end: String,
+    /// IANA name of timezone in which event times are returned.
+    /// Times are returned in UTC when not set
+    #[serde(alias = "timeZone")]
+    tz: Option<String>,
+    /// When set, events are returned together with problems of degraded calendars
+    #[serde(default)]
+    diagnostics: bool,
+    /// When set, each event lists calendars of feed it is found in
+    #[serde(default)]
+    sources: bool,
 }
 
 pub async fn get_events_feed(
+    AuthorizedFeed(access): AuthorizedFeed,
     Query(params): Query<EventsQuery>,
     Extension(feed): Extension<FeedService>,
 ) -> ApiResult<impl IntoResponse> {
-    let start: DateTime<Utc> = params.start.parse().context("Invalid start datetime")?;
-    let end: DateTime<Utc> = params.end.parse().context("Invalid end datetime")?;
+    let tz = params.tz.as_deref().map(parse_timezone).transpose()?;
+    let (start, end) = parse_range(&params.start, &params.end, tz.unwrap_or(Tz::UTC))?;
 
-    let events = feed.get_feed(&params.token, start, end).await?;
+    let feed = feed
+        .get_feed(&access, start, end, tz.unwrap_or(Tz::UTC))
+        .await?;
 
-    #[derive(Clone, Debug, Serialize)]
-    struct EventDto {
-        start: String,
-        end: String,
-        title: String,
-    }
-
-    let fmt = "%Y-%m-%dT%H:%M:%SZ";
-    let fmt_date = "%Y-%m-%d";
+    // some calendars failed, so client receives only part of events
+    let status = if feed.failed_calendars() > 0 {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
 
-    let events: Vec<_> = events
+    let events: Vec<_> = feed
+        .events
         .into_iter()
-        .map(|event| {
-            let (start, end) = event.range.either(
-                |start, end| {
-                    (
-                        start.format(fmt_date).to_string(),
-                        end.format(fmt_date).to_string(),
-                    )
-                },
-                |start, end| (start.format(fmt).to_string(), end.format(fmt).to_string()),
-            );
-            EventDto {
-                start,
-                end,
-                title: event.summary,
-            }
+        .map(|event| SourcedEventDto {
+            sources: params.sources.then_some(event.sources),
+            event: EventDto::new(event.event, tz),
         })
         .collect();
 
-    Ok(axum::Json(events))
+    let body = if params.diagnostics {
+        json!({
+            "events": events,
+            "diagnostics": feed.diagnostics,
+        })
+    } else {
+        json!(events)
+    };
+
+    Ok((status, axum::Json(body)))
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Ics,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergedEventsQuery {
+    start: String,
+    end: String,
+    /// IANA name of timezone in which event times are returned.
+    /// Times are returned in UTC when not set
+    #[serde(alias = "timeZone")]
+    tz: Option<String>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Feed which events are merged into response
+#[derive(Debug, Serialize)]
+struct SourceDto {
+    name: String,
+    color: &'static str,
+    /// Set when all calendars of feed failed to load
+    unavailable: bool,
+    diagnostics: Vec<CalendarDiagnostics>,
+}
+
+#[derive(Debug, Serialize)]
+struct MergedEventDto {
+    #[serde(flatten)]
+    event: EventDto,
+    /// Name of feed this event belongs to
+    source: String,
+    color: &'static str,
+}
+
+/// Returns events of all feeds of request tokens in single response.
+/// Feeds are fetched in parallel, each event is tagged with its feed
+pub async fn get_merged_events(
+    RequestTokens(tokens): RequestTokens,
+    Query(params): Query<MergedEventsQuery>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(feed_service): Extension<FeedService>,
+) -> ApiResult<Response> {
+    let tz = params.tz.as_deref().map(parse_timezone).transpose()?;
+    let (start, end) = parse_range(&params.start, &params.end, tz.unwrap_or(Tz::UTC))?;
+
+    let config = config.get();
+    let accesses = tokens
+        .iter()
+        .map(|t| FeedAccess::resolve(config.clone(), t))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(ApiError::Unauthorized)?;
+
+    let feeds = feed_service
+        .get_feeds(&accesses, start, end, tz.unwrap_or(Tz::UTC))
+        .await;
+
+    let mut sources = vec![];
+    let mut events = vec![];
+    let mut errors = vec![];
+    for (i, (access, feed)) in accesses.iter().zip(feeds).enumerate() {
+        let name = access.feed().name.clone();
+        let color = FEED_COLORS[i % FEED_COLORS.len()];
+        match feed {
+            Ok(feed) => {
+                events.extend(feed.events.into_iter().map(|e| (i, e.event)));
+                sources.push(SourceDto {
+                    name,
+                    color,
+                    unavailable: false,
+                    diagnostics: feed.diagnostics,
+                });
+            }
+            Err(FeedError::Unavailable(err)) => {
+                errors.push(err);
+                sources.push(SourceDto {
+                    name,
+                    color,
+                    unavailable: true,
+                    diagnostics: vec![],
+                });
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    if errors.len() == sources.len() {
+        return Err(ApiError::UpstreamUnavailable(errors.swap_remove(0)));
+    }
+
+    // some feeds or calendars failed, so client receives only part of events.
+    // Calendar apps treat other status than 200 as failed subscription, so ics is always 200
+    let degraded = !errors.is_empty()
+        || sources
+            .iter()
+            .flat_map(|s| &s.diagnostics)
+            .any(|d| d.error.is_some());
+    let status = match params.format {
+        ExportFormat::Json if degraded => StatusCode::PARTIAL_CONTENT,
+        _ => StatusCode::OK,
+    };
+
+    events.sort_by_key(|(_, event)| event.range.start().into_datetime());
+    let response = match params.format {
+        ExportFormat::Json => {
+            let events: Vec<_> = events
+                .into_iter()
+                .map(|(i, event)| MergedEventDto {
+                    event: EventDto::new(event, tz),
+                    source: sources[i].name.clone(),
+                    color: sources[i].color,
+                })
+                .collect();
+            let body = json!({
+                "events": events,
+                "sources": sources,
+            });
+            (status, axum::Json(body)).into_response()
+        }
+        ExportFormat::Ics => {
+            // clients show single calendar, so source is kept in summary
+            let events: Vec<_> = events
+                .into_iter()
+                .map(|(i, event)| PrimitiveEvent {
+                    summary: format!("{}: {}", sources[i].name, event.summary),
+                    // the same event may come from several feeds
+                    uid: format!("{}/{}", sources[i].name, event.uid),
+                    ..event
+                })
+                .collect();
+            let title = sources
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let body = ics::write_calendar(&title, &events);
+            (
+                status,
+                [(CONTENT_TYPE, "text/calendar; charset=utf-8")],
+                body,
+            )
+                .into_response()
+        }
+    };
+    Ok(response)
+}
+
+#[derive(Debug, Serialize)]
+struct SourcedEventDto {
+    #[serde(flatten)]
+    event: EventDto,
+    /// Calendars of feed with this event, only returned when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sources: Option<Vec<EventSource>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::config::{ConfigBuilder, SharedConfig};
+    use crate::routes::feeds::get_merged_events;
+    use crate::service::feeds::FeedService;
+
+    const TOKEN_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const TOKEN_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const TOKEN_DOWN: &str = "cccccccccccccccccccccccccccccccccccccccccccc";
+
+    /// Calendar with hour long events on 2024-03-04, given as uid, start hour and summary
+    fn calendar(events: &[(&str, u32, &str)]) -> String {
+        let events: String = events
+            .iter()
+            .map(|(uid, hour, summary)| {
+                format!(
+                    "BEGIN:VEVENT\nUID:{}\nDTSTART:20240304T{:02}0000Z\nDTEND:20240304T{:02}0000Z\nSUMMARY:{}\nEND:VEVENT\n",
+                    uid, hour, hour + 1, summary
+                )
+            })
+            .collect();
+        format!("BEGIN:VCALENDAR\nVERSION:2.0\n{}END:VCALENDAR\n", events)
+    }
+
+    /// Merged events endpoint with feeds `a`, `b` and `down`. Calendars are served by local
+    /// server, calendar `down.ics` always fails, so feed `b` is degraded and `down` unavailable
+    async fn router() -> Router {
+        let upstream = Router::new()
+            .route(
+                "/a.ics",
+                get(|| async { calendar(&[("late", 10, "Late"), ("early", 8, "Early")]) }),
+            )
+            .route(
+                "/b.ics",
+                get(|| async { calendar(&[("review", 9, "Review")]) }),
+            )
+            .route(
+                "/down.ics",
+                get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let url = |name: &str| format!("http://{}/{}.ics", addr, name);
+        let mut config = ConfigBuilder::default()
+            .feed("a", TOKEN_A, &TOKEN_A.replace('a', "p"), &[&url("a")])
+            .feed(
+                "b",
+                TOKEN_B,
+                &TOKEN_B.replace('b', "q"),
+                &[&url("b"), &url("down")],
+            )
+            .feed(
+                "down",
+                TOKEN_DOWN,
+                &TOKEN_DOWN.replace('c', "r"),
+                &[&url("down")],
+            )
+            .build();
+        config.fetch.retries = 0;
+        let config = SharedConfig::new(config);
+        let feed_service = FeedService::new(config.clone()).unwrap();
+
+        Router::new()
+            .route("/events/merged", get(get_merged_events))
+            .layer(Extension(config))
+            .layer(Extension(feed_service))
+    }
+
+    async fn get_merged(router: Router, tokens: &[&str], format: &str) -> (StatusCode, String) {
+        let request = Request::get(format!(
+            "/events/merged?start=2024-03-04&end=2024-03-05&format={}",
+            format
+        ))
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", tokens.join(",")),
+        )
+        .body(Body::empty())
+        .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn merged_events_are_sorted_and_tagged_with_feed() {
+        let (status, body) = get_merged(router().await, &[TOKEN_A], "json").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body_ab) = get_merged(router().await, &[TOKEN_A, TOKEN_B], "json").await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 2);
+        let body: Value = serde_json::from_str(&body_ab).unwrap();
+        let events: Vec<_> = body["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["title"].as_str().unwrap(), e["source"].as_str().unwrap()))
+            .collect();
+        assert_eq!(events, [("Early", "a"), ("Review", "b"), ("Late", "a")]);
+        assert_ne!(body["events"][0]["color"], body["events"][1]["color"]);
+        assert_eq!(body["sources"][1]["diagnostics"][0]["calendar"], 1);
+    }
+
+    #[tokio::test]
+    async fn unavailable_feeds_are_reported() {
+        let (status, body) = get_merged(router().await, &[TOKEN_A, TOKEN_DOWN], "json").await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["sources"][0]["unavailable"], false);
+        assert_eq!(body["sources"][1]["unavailable"], true);
+
+        let (status, _) = get_merged(router().await, &[TOKEN_DOWN], "json").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn unknown_token_asks_for_bearer_token() {
+        let request = Request::get("/events/merged?start=2024-03-04&end=2024-03-05")
+            .header(header::AUTHORIZATION, "Bearer unknown")
+            .body(Body::empty())
+            .unwrap();
+        let response = router().await.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn merged_ics_is_complete_response() {
+        let (status, body) = get_merged(router().await, &[TOKEN_A, TOKEN_B], "ics").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("SUMMARY:a: Early"), "{}", body);
+        assert!(body.contains("SUMMARY:b: Review"), "{}", body);
+        assert!(body.contains("UID:b/review-20240304T090000Z"), "{}", body);
+    }
 }
@@ -1,5 +1,8 @@
+mod admin;
+mod auth;
 mod error_response;
 mod feeds;
+mod pages;
 mod setup;
 
 pub use setup::create_router;
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use askama::Template;
+use askama_axum::{IntoResponse, Response};
+use axum::extract::Path;
+use axum::response::Redirect;
+use axum::{Extension, Form};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+use hyper::StatusCode;
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::config::{CalendarConfig, SharedConfig};
+use crate::routes::auth::{PageAuth, PageSession, ADMIN_COOKIE, SESSION_COOKIE};
+use crate::routes::error_response::ApiError;
+use crate::service::admin::{
+    AdminError, AdminService, FeedInfo, FeedSource, IssuedToken, NewFeed, NewToken,
+};
+use crate::service::feeds::{CalendarStatus, FeedService};
+use crate::service::tokens::Privacy;
+use crate::service::users::UserService;
+
+#[derive(Template)]
+#[template(path = "admin_login.html")]
+pub struct AdminLoginTemplate {
+    error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate {
+    error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "feeds.html")]
+pub struct FeedsTemplate {
+    /// Path of this page, forms are posted under it
+    base: &'static str,
+    logout: &'static str,
+    /// Logged in user, not set for admin
+    user: Option<String>,
+    feeds: Vec<FeedView>,
+    /// Feeds can be created only when store is configured
+    has_store: bool,
+    error: Option<String>,
+}
+
+pub struct FeedView {
+    info: FeedInfo,
+    /// Only feeds from store can be changed
+    editable: bool,
+    calendars: Vec<CalendarView>,
+}
+
+pub struct CalendarView {
+    name: String,
+    state: String,
+    failures: u32,
+    last_fetched: String,
+    retry_in: Option<u64>,
+}
+
+/// Shows issued tokens as share links. Tokens are not stored, so they are shown once
+#[derive(Template)]
+#[template(path = "share_links.html")]
+pub struct ShareLinksTemplate {
+    base: &'static str,
+    feed: String,
+    tokens: Vec<IssuedToken>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminLoginForm {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    name: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedForm {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarForm {
+    url: String,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenForm {
+    label: String,
+    privacy: Privacy,
+}
+
+pub async fn get_admin_login() -> impl IntoResponse {
+    AdminLoginTemplate { error: None }
+}
+
+pub async fn admin_login(
+    jar: CookieJar,
+    Extension(config): Extension<SharedConfig>,
+    Extension(users): Extension<UserService>,
+    Form(form): Form<AdminLoginForm>,
+) -> Response {
+    let Some(session) = users.admin_login(&form.token).await else {
+        let page = AdminLoginTemplate {
+            error: Some("Invalid token".to_string()),
+        };
+        return (StatusCode::UNAUTHORIZED, page).into_response();
+    };
+
+    let cookie = session_cookie(&config, ADMIN_COOKIE, session, "/admin");
+    (jar.add(cookie), Redirect::to("/admin/ui")).into_response()
+}
+
+pub async fn admin_logout(
+    jar: CookieJar,
+    Extension(users): Extension<UserService>,
+) -> impl IntoResponse {
+    if let Some(session) = jar.get(ADMIN_COOKIE) {
+        users.logout(session.value()).await;
+    }
+    let cookie = Cookie::build(ADMIN_COOKIE).path("/admin");
+    (jar.remove(cookie), Redirect::to("/admin/login"))
+}
+
+pub async fn get_login() -> impl IntoResponse {
+    LoginTemplate { error: None }
+}
+
+pub async fn login(
+    jar: CookieJar,
+    Extension(config): Extension<SharedConfig>,
+    Extension(users): Extension<UserService>,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let Some(session) = users.login(form.name.trim(), &form.password).await else {
+        let page = LoginTemplate {
+            error: Some("Invalid name or password".to_string()),
+        };
+        return (StatusCode::UNAUTHORIZED, page).into_response();
+    };
+
+    let cookie = session_cookie(&config, SESSION_COOKIE, session, "/");
+    (jar.add(cookie), Redirect::to("/account")).into_response()
+}
+
+pub async fn logout(jar: CookieJar, Extension(users): Extension<UserService>) -> impl IntoResponse {
+    if let Some(session) = jar.get(SESSION_COOKIE) {
+        users.logout(session.value()).await;
+    }
+    let cookie = Cookie::build(SESSION_COOKIE).path("/");
+    (jar.remove(cookie), Redirect::to("/login"))
+}
+
+pub async fn get_feeds<A: PageAuth>(
+    auth: A,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+) -> impl IntoResponse {
+    let session: PageSession = auth.into();
+    feeds_page(&session, &admin, &feed_service, None).await
+}
+
+pub async fn create_feed<A: PageAuth>(
+    auth: A,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+    Form(form): Form<FeedForm>,
+) -> Response {
+    let session: PageSession = auth.into();
+    let feed = NewFeed {
+        name: form.name.trim().to_string(),
+        owner: None,
+        calendars: vec![],
+        include: vec![],
+        tokens: vec![],
+    };
+    let name = feed.name.clone();
+
+    match admin.create_feed(session.actor(), feed).await {
+        Ok(tokens) => share_links(&session, name, tokens),
+        Err(err) => error_page(&session, &admin, &feed_service, err).await,
+    }
+}
+
+pub async fn add_calendar<A: PageAuth>(
+    auth: A,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+    Form(form): Form<CalendarForm>,
+) -> Response {
+    let session: PageSession = auth.into();
+    let calendar = CalendarConfig {
+        url: Secret::new(form.url.trim().to_string()),
+        name: form.name.filter(|name| !name.trim().is_empty()),
+        refresh_interval: None,
+        ttl: None,
+        connect_timeout: None,
+        read_timeout: None,
+        max_size: None,
+        retries: None,
+        privacy: None,
+    };
+
+    match admin.add_calendar(session.actor(), &name, calendar).await {
+        Ok(_) => Redirect::to(session.base()).into_response(),
+        Err(err) => error_page(&session, &admin, &feed_service, err).await,
+    }
+}
+
+pub async fn add_token<A: PageAuth>(
+    auth: A,
+    Path(name): Path<String>,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+    Form(form): Form<TokenForm>,
+) -> Response {
+    let session: PageSession = auth.into();
+    let token = NewToken {
+        label: form.label.trim().to_string(),
+        privacy: form.privacy,
+        expires_at: None,
+    };
+
+    match admin.add_token(session.actor(), &name, token).await {
+        Ok(token) => share_links(&session, name, vec![token]),
+        Err(err) => error_page(&session, &admin, &feed_service, err).await,
+    }
+}
+
+pub async fn rotate_token<A: PageAuth>(
+    auth: A,
+    Path((name, label)): Path<(String, String)>,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+) -> Response {
+    let session: PageSession = auth.into();
+    match admin.rotate_token(session.actor(), &name, &label).await {
+        Ok(token) => share_links(&session, name, vec![token]),
+        Err(err) => error_page(&session, &admin, &feed_service, err).await,
+    }
+}
+
+pub async fn revoke_token<A: PageAuth>(
+    auth: A,
+    Path((name, label)): Path<(String, String)>,
+    Extension(admin): Extension<AdminService>,
+    Extension(feed_service): Extension<FeedService>,
+) -> Response {
+    let session: PageSession = auth.into();
+    match admin.revoke_token(session.actor(), &name, &label).await {
+        Ok(()) => Redirect::to(session.base()).into_response(),
+        Err(err) => error_page(&session, &admin, &feed_service, err).await,
+    }
+}
+
+/// Strict same site cookie is not sent with cross-site forms, so pages need no csrf tokens
+fn session_cookie(
+    config: &SharedConfig,
+    name: &'static str,
+    value: String,
+    path: &'static str,
+) -> Cookie<'static> {
+    Cookie::build((name, value))
+        .path(path)
+        .http_only(true)
+        .secure(config.get().server.secure_cookies)
+        .same_site(SameSite::Strict)
+        .build()
+}
+
+fn share_links(session: &PageSession, feed: String, tokens: Vec<IssuedToken>) -> Response {
+    ShareLinksTemplate {
+        base: session.base(),
+        feed,
+        tokens,
+    }
+    .into_response()
+}
+
+async fn feeds_page(
+    session: &PageSession,
+    admin: &AdminService,
+    feed_service: &FeedService,
+    error: Option<String>,
+) -> FeedsTemplate {
+    let mut statuses: HashMap<(String, usize), CalendarStatus> = feed_service
+        .calendar_statuses()
+        .into_iter()
+        .map(|status| ((status.feed.clone(), status.calendar), status))
+        .collect();
+
+    let feeds = admin
+        .list_feeds(session.actor())
+        .await
+        .into_iter()
+        .map(|info| {
+            let calendars = info
+                .calendars
+                .iter()
+                .map(|calendar| {
+                    let status = statuses.remove(&(info.name.clone(), calendar.index));
+                    CalendarView {
+                        name: calendar
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| format!("#{}", calendar.index + 1)),
+                        state: status
+                            .as_ref()
+                            .map_or("unknown".to_string(), |s| format!("{:?}", s.state)),
+                        failures: status.as_ref().map_or(0, |s| s.failures),
+                        last_fetched: status
+                            .as_ref()
+                            .and_then(|s| s.last_fetched)
+                            .map_or("never".to_string(), |time| {
+                                time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                            }),
+                        retry_in: status.and_then(|s| s.retry_in),
+                    }
+                })
+                .collect();
+
+            FeedView {
+                editable: info.source == FeedSource::Store,
+                info,
+                calendars,
+            }
+        })
+        .collect();
+
+    let (logout, user) = match session {
+        PageSession::Admin => ("/admin/logout", None),
+        PageSession::User(name) => ("/logout", Some(name.clone())),
+    };
+    FeedsTemplate {
+        base: session.base(),
+        logout,
+        user,
+        feeds,
+        has_store: admin.has_store().await,
+        error,
+    }
+}
+
+/// Shows feeds page with error of failed change
+async fn error_page(
+    session: &PageSession,
+    admin: &AdminService,
+    feed_service: &FeedService,
+    err: AdminError,
+) -> Response {
+    let err = ApiError::from(err);
+    let status = err.status_code();
+    if status.is_server_error() {
+        tracing::error!("{:?}", err);
+    }
+
+    let page = feeds_page(session, admin, feed_service, Some(err.to_message())).await;
+    (status, page).into_response()
+}
@@ -1,18 +1,88 @@
-use axum::{routing::get, Extension, Router};
+use axum::routing::{delete, get, post, put};
+use axum::{Extension, Router};
 
 use tower::ServiceBuilder;
 
-use crate::config::AppConfig;
-use crate::routes::feeds;
+use crate::config::SharedConfig;
+use crate::routes::auth::{AdminSession, PageAuth, UserSession};
+use crate::routes::{admin, feeds, pages};
+use crate::service::admin::AdminService;
 use crate::service::feeds::FeedService;
+use crate::service::users::UserService;
 
-pub fn create_router(config: AppConfig, feed_service: FeedService) -> Router {
+pub fn create_router(
+    config: SharedConfig,
+    feed_service: FeedService,
+    admin_service: AdminService,
+    user_service: UserService,
+) -> Router {
     Router::new()
         .route("/events", get(feeds::get_events_feed))
+        .route("/events/merged", get(feeds::get_merged_events))
         .route("/feeds/feed.html", get(feeds::get_html_feed))
+        .route("/feeds/:token/events", get(feeds::get_events_feed))
+        .route("/feeds/:token/feed.html", get(feeds::get_html_feed))
+        .route("/admin/calendars", get(admin::get_calendar_statuses))
+        .route("/admin/lint", post(admin::lint_calendar))
+        .route(
+            "/admin/feeds",
+            get(admin::list_feeds).post(admin::create_feed),
+        )
+        .route(
+            "/admin/feeds/:name",
+            put(admin::update_feed).delete(admin::delete_feed),
+        )
+        .route("/admin/feeds/:name/calendars", post(admin::add_calendar))
+        .route(
+            "/admin/feeds/:name/calendars/:index",
+            delete(admin::remove_calendar),
+        )
+        .route("/admin/feeds/:name/tokens", post(admin::add_token))
+        .route(
+            "/admin/feeds/:name/tokens/:label",
+            delete(admin::revoke_token),
+        )
+        .route(
+            "/admin/feeds/:name/tokens/:label/rotate",
+            post(admin::rotate_token),
+        )
+        .route(
+            "/admin/login",
+            get(pages::get_admin_login).post(pages::admin_login),
+        )
+        .route("/admin/logout", post(pages::admin_logout))
+        .route("/login", get(pages::get_login).post(pages::login))
+        .route("/logout", post(pages::logout))
+        .merge(feed_pages::<AdminSession>("/admin/ui"))
+        .merge(feed_pages::<UserSession>("/account"))
         .layer(
             ServiceBuilder::new()
                 .layer(Extension(config))
-                .layer(Extension(feed_service)),
+                .layer(Extension(feed_service))
+                .layer(Extension(admin_service))
+                .layer(Extension(user_service)),
+        )
+}
+
+/// Feed management pages, the same for admin and users. Session is checked with given extractor
+fn feed_pages<A: PageAuth>(base: &str) -> Router {
+    Router::new()
+        .route(base, get(pages::get_feeds::<A>))
+        .route(&format!("{}/feeds", base), post(pages::create_feed::<A>))
+        .route(
+            &format!("{}/feeds/:name/calendars", base),
+            post(pages::add_calendar::<A>),
+        )
+        .route(
+            &format!("{}/feeds/:name/tokens", base),
+            post(pages::add_token::<A>),
+        )
+        .route(
+            &format!("{}/feeds/:name/tokens/:label/rotate", base),
+            post(pages::rotate_token::<A>),
+        )
+        .route(
+            &format!("{}/feeds/:name/tokens/:label/revoke", base),
+            post(pages::revoke_token::<A>),
         )
 }
@@ -0,0 +1,639 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::{AppConfig, CalendarConfig, FeedConfig, TokenConfig};
+use crate::service::feeds::FeedService;
+use crate::service::store::FeedStore;
+use crate::service::tokens::{self, Privacy, DEFAULT_TOKEN_LENGTH};
+use crate::service::validation::InvalidConfig;
+
+/// Manages feeds at runtime. Feeds from config file are read only,
+/// feeds created with admin API are kept in store
+#[derive(Clone)]
+pub struct AdminService {
+    state: Arc<Mutex<AdminState>>,
+    feed_service: FeedService,
+}
+
+struct AdminState {
+    /// Config as read from files, without stored feeds
+    base: AppConfig,
+    store: Option<FeedStore>,
+    stored: Vec<FeedConfig>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("Feed '{0}' not found")]
+    FeedNotFound(String),
+
+    #[error("Calendar {0} not found")]
+    CalendarNotFound(usize),
+
+    #[error("Token '{0}' not found")]
+    TokenNotFound(String),
+
+    #[error("Feed '{0}' is defined in config file and can't be changed")]
+    ReadOnly(String),
+
+    #[error("Feed store is not configured")]
+    NoStore,
+
+    #[error(transparent)]
+    Invalid(#[from] InvalidConfig),
+
+    #[error("Change conflicts with other feeds")]
+    Conflict,
+
+    #[error(transparent)]
+    Store(#[from] anyhow::Error),
+}
+
+/// Who manages feeds. Users see and change only feeds they own
+#[derive(Clone, Copy, Debug)]
+pub enum Actor<'a> {
+    Admin,
+    User(&'a str),
+}
+
+impl Actor<'_> {
+    fn can_manage(&self, feed: &FeedConfig) -> bool {
+        match self {
+            Actor::Admin => true,
+            Actor::User(name) => feed.owner.as_deref() == Some(*name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedSource {
+    /// Feed is defined in config file
+    Config,
+
+    /// Feed is created with admin API
+    Store,
+}
+
+/// Feed without secrets: calendar urls and tokens are not shown
+#[derive(Debug, Serialize)]
+pub struct FeedInfo {
+    pub name: String,
+    pub owner: Option<String>,
+    pub source: FeedSource,
+    pub calendars: Vec<CalendarInfo>,
+    pub include: Vec<String>,
+    pub tokens: Vec<TokenInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarInfo {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenInfo {
+    pub label: String,
+    pub privacy: Privacy,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewFeed {
+    pub name: String,
+    /// Set only by admin, feeds created by users are owned by them
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub calendars: Vec<CalendarConfig>,
+    /// Names of feeds which calendars are shown in this feed
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Private and public tokens are issued when empty
+    #[serde(default)]
+    pub tokens: Vec<NewToken>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedUpdate {
+    pub name: Option<String>,
+    pub calendars: Option<Vec<CalendarConfig>>,
+    pub include: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewToken {
+    pub label: String,
+    pub privacy: Privacy,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Issued token. Only its digest is stored, so it is shown once
+#[derive(Debug, Serialize)]
+pub struct IssuedToken {
+    pub label: String,
+    pub privacy: Privacy,
+    pub token: String,
+}
+
+impl AdminService {
+    pub fn new(
+        base: AppConfig,
+        store: Option<FeedStore>,
+        stored: Vec<FeedConfig>,
+        feed_service: FeedService,
+    ) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(AdminState {
+                base,
+                store,
+                stored,
+            })),
+            feed_service,
+        }
+    }
+
+    /// Replaces config read from files, stored feeds are kept
+    pub async fn reload(&self, base: AppConfig) -> Result<(), AdminError> {
+        let mut state = self.state.lock().await;
+        let config = merge_feeds(&base, &state.stored)?;
+        state.base = base;
+        self.feed_service.reload(config).await;
+        Ok(())
+    }
+
+    /// Feeds can be changed only when store is configured
+    pub async fn has_store(&self) -> bool {
+        self.state.lock().await.store.is_some()
+    }
+
+    pub async fn list_feeds(&self, actor: Actor<'_>) -> Vec<FeedInfo> {
+        let state = self.state.lock().await;
+        let config_feeds = state
+            .base
+            .feeds
+            .iter()
+            .filter(|feed| actor.can_manage(feed))
+            .map(|feed| FeedInfo::new(feed, FeedSource::Config));
+        let stored_feeds = state
+            .stored
+            .iter()
+            .filter(|feed| actor.can_manage(feed))
+            .map(|feed| FeedInfo::new(feed, FeedSource::Store));
+        config_feeds.chain(stored_feeds).collect()
+    }
+
+    /// Creates feed and returns its tokens
+    pub async fn create_feed(
+        &self,
+        actor: Actor<'_>,
+        new: NewFeed,
+    ) -> Result<Vec<IssuedToken>, AdminError> {
+        let owner = match actor {
+            Actor::Admin => new.owner,
+            Actor::User(name) => Some(name.to_string()),
+        };
+        self.modify(actor, |stored| {
+            let tokens = if new.tokens.is_empty() {
+                vec![
+                    NewToken {
+                        label: "private".to_string(),
+                        privacy: Privacy::Private,
+                        expires_at: None,
+                    },
+                    NewToken {
+                        label: "public".to_string(),
+                        privacy: Privacy::Public,
+                        expires_at: None,
+                    },
+                ]
+            } else {
+                new.tokens
+            };
+
+            let (configs, issued) = tokens.into_iter().map(issue_token).unzip();
+            stored.push(FeedConfig {
+                name: new.name,
+                owner,
+                tokens: configs,
+                calendars: new.calendars,
+                include: new.include,
+            });
+            Ok(issued)
+        })
+        .await
+    }
+
+    pub async fn update_feed(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        update: FeedUpdate,
+    ) -> Result<(), AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            if let Some(name) = update.name {
+                feed.name = name;
+            }
+            if let Some(calendars) = update.calendars {
+                feed.calendars = calendars;
+            }
+            if let Some(include) = update.include {
+                feed.include = include;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn delete_feed(&self, actor: Actor<'_>, name: &str) -> Result<(), AdminError> {
+        self.modify(actor, |stored| {
+            let i = find_feed(stored, actor, name)?;
+            stored.remove(i);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Adds calendar to feed and returns its index
+    pub async fn add_calendar(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        calendar: CalendarConfig,
+    ) -> Result<usize, AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            feed.calendars.push(calendar);
+            Ok(feed.calendars.len() - 1)
+        })
+        .await
+    }
+
+    pub async fn remove_calendar(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        index: usize,
+    ) -> Result<(), AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            if index >= feed.calendars.len() {
+                return Err(AdminError::CalendarNotFound(index));
+            }
+            feed.calendars.remove(index);
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn add_token(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        token: NewToken,
+    ) -> Result<IssuedToken, AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            let (config, issued) = issue_token(token);
+            feed.tokens.push(config);
+            Ok(issued)
+        })
+        .await
+    }
+
+    /// Replaces token with new one keeping its label, privacy and expiration
+    pub async fn rotate_token(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        label: &str,
+    ) -> Result<IssuedToken, AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            let token = find_token(feed, label)?;
+            let (config, issued) = issue_token(NewToken {
+                label: token.label.clone(),
+                privacy: token.privacy,
+                expires_at: token.expires_at,
+            });
+            *token = config;
+            Ok(issued)
+        })
+        .await
+    }
+
+    pub async fn revoke_token(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        label: &str,
+    ) -> Result<(), AdminError> {
+        self.modify_feed(actor, name, |feed| {
+            find_token(feed, label)?.revoked = true;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn modify_feed<T>(
+        &self,
+        actor: Actor<'_>,
+        name: &str,
+        f: impl FnOnce(&mut FeedConfig) -> Result<T, AdminError>,
+    ) -> Result<T, AdminError> {
+        self.modify(actor, |stored| {
+            let i = find_feed(stored, actor, name)?;
+            f(&mut stored[i])
+        })
+        .await
+    }
+
+    /// Applies change to stored feeds. Change is saved and applied only when
+    /// resulting config is valid
+    async fn modify<T>(
+        &self,
+        actor: Actor<'_>,
+        f: impl FnOnce(&mut Vec<FeedConfig>) -> Result<T, AdminError>,
+    ) -> Result<T, AdminError> {
+        let mut state = self.state.lock().await;
+        let store = state.store.clone().ok_or(AdminError::NoStore)?;
+
+        let mut stored = state.stored.clone();
+        let result = f(&mut stored).map_err(|err| match err {
+            AdminError::FeedNotFound(name)
+                if state
+                    .base
+                    .feeds
+                    .iter()
+                    .any(|f| f.name == name && actor.can_manage(f)) =>
+            {
+                AdminError::ReadOnly(name)
+            }
+            err => err,
+        })?;
+
+        let config = merge_feeds(&state.base, &stored).map_err(|err| {
+            let feeds: Vec<_> = state.base.feeds.iter().chain(&stored).collect();
+            hide_foreign_issues(err, actor, &feeds)
+        })?;
+        store.save(&stored).await?;
+        state.stored = stored;
+        self.feed_service.reload(config).await;
+        Ok(result)
+    }
+}
+
+impl FeedInfo {
+    fn new(feed: &FeedConfig, source: FeedSource) -> Self {
+        Self {
+            name: feed.name.clone(),
+            owner: feed.owner.clone(),
+            source,
+            calendars: feed
+                .calendars
+                .iter()
+                .enumerate()
+                .map(|(index, calendar)| CalendarInfo {
+                    index,
+                    name: calendar.name.clone(),
+                })
+                .collect(),
+            include: feed.include.clone(),
+            tokens: feed
+                .tokens
+                .iter()
+                .map(|token| TokenInfo {
+                    label: token.label.clone(),
+                    privacy: token.privacy,
+                    expires_at: token.expires_at,
+                    revoked: token.revoked,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns config with stored feeds added to feeds of base config
+pub fn merge_feeds(base: &AppConfig, stored: &[FeedConfig]) -> Result<AppConfig, InvalidConfig> {
+    let feeds = base.feeds.iter().chain(stored).cloned().collect();
+    let config = base.with_feeds(feeds);
+    if !stored.is_empty() {
+        config.validate().into_result()?;
+    }
+    Ok(config)
+}
+
+/// Users see only issues of their own feeds. Other issues may reveal settings of other feeds,
+/// so they are reported as conflict without details
+fn hide_foreign_issues(err: InvalidConfig, actor: Actor<'_>, feeds: &[&FeedConfig]) -> AdminError {
+    if let Actor::Admin = actor {
+        return AdminError::Invalid(err);
+    }
+
+    let own: Vec<String> = feeds
+        .iter()
+        .enumerate()
+        .filter(|(_, feed)| actor.can_manage(feed))
+        .map(|(i, _)| format!("feeds[{}]", i))
+        .collect();
+    let InvalidConfig(issues) = err;
+    let issues: Vec<_> = issues
+        .into_iter()
+        .filter(|issue| {
+            own.iter()
+                .any(|path| issue.path == *path || issue.path.starts_with(&format!("{}.", path)))
+        })
+        .collect();
+
+    if issues.is_empty() {
+        AdminError::Conflict
+    } else {
+        AdminError::Invalid(InvalidConfig(issues))
+    }
+}
+
+/// Feeds of other users are reported as missing, so their names aren't revealed
+fn find_feed(feeds: &[FeedConfig], actor: Actor<'_>, name: &str) -> Result<usize, AdminError> {
+    feeds
+        .iter()
+        .position(|feed| feed.name == name && actor.can_manage(feed))
+        .ok_or_else(|| AdminError::FeedNotFound(name.to_string()))
+}
+
+fn find_token<'a>(
+    feed: &'a mut FeedConfig,
+    label: &str,
+) -> Result<&'a mut TokenConfig, AdminError> {
+    feed.tokens
+        .iter_mut()
+        .find(|token| token.label == label)
+        .ok_or_else(|| AdminError::TokenNotFound(label.to_string()))
+}
+
+/// Generates token, only its digest is kept in config
+fn issue_token(new: NewToken) -> (TokenConfig, IssuedToken) {
+    let token = tokens::generate_token(DEFAULT_TOKEN_LENGTH);
+    let config = TokenConfig {
+        label: new.label.clone(),
+        token: Secret::new(tokens::hashed_token(&token)),
+        privacy: new.privacy,
+        expires_at: new.expires_at,
+        revoked: false,
+    };
+    let issued = IssuedToken {
+        label: new.label,
+        privacy: new.privacy,
+        token,
+    };
+    (config, issued)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono_tz::Tz;
+
+    use secrecy::Secret;
+
+    use crate::config::{AppConfig, CalendarConfig, ConfigBuilder, FeedBuilder, SharedConfig};
+    use crate::service::admin::{Actor, AdminError, AdminService, FeedSource, FeedUpdate, NewFeed};
+    use crate::service::feeds::{FeedError, FeedService};
+    use crate::service::store::FeedStore;
+
+    fn config() -> AppConfig {
+        ConfigBuilder::default()
+            .user("alice")
+            .user("bob")
+            .feed("base", &"a".repeat(40), &"b".repeat(40), &[])
+            .build()
+    }
+
+    /// Service with stored feeds of alice and bob, and feed of admin which includes alice's feed
+    fn service(store: &str) -> (AdminService, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("icaliada-{}-{}.json", store, std::process::id()));
+        let token = |c: &str| c.repeat(40);
+        let stored = vec![
+            FeedBuilder::new("a", &token("c"), &token("d"), &["http://a"])
+                .owner("alice")
+                .build(),
+            FeedBuilder::new("b", &token("e"), &token("f"), &["http://b"])
+                .owner("bob")
+                .build(),
+            FeedBuilder::new("team", &token("g"), &token("h"), &[])
+                .include(&["a"])
+                .build(),
+        ];
+        let feed_service = FeedService::new(SharedConfig::new(config())).unwrap();
+        let admin = AdminService::new(config(), Some(FeedStore::new(&path)), stored, feed_service);
+        (admin, path)
+    }
+
+    fn calendar(url: &str) -> CalendarConfig {
+        CalendarConfig {
+            url: Secret::new(url.to_string()),
+            name: None,
+            refresh_interval: None,
+            ttl: None,
+            connect_timeout: None,
+            read_timeout: None,
+            max_size: None,
+            retries: None,
+            privacy: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn created_feed_is_stored_and_served() {
+        let path = std::env::temp_dir().join(format!("icaliada-store-{}.json", std::process::id()));
+        let store = FeedStore::new(&path);
+        let feed_service = FeedService::new(SharedConfig::new(config())).unwrap();
+        let admin = AdminService::new(config(), Some(store.clone()), vec![], feed_service.clone());
+
+        let new: NewFeed = serde_json::from_str(r#"{"name": "new"}"#).unwrap();
+        let tokens = admin.create_feed(Actor::Admin, new).await.unwrap();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(store.load().await.unwrap()[0].name, "new");
+        let feeds = admin.list_feeds(Actor::Admin).await;
+        assert_eq!(feeds[1].source, FeedSource::Store);
+        let now = Utc::now();
+        let result = feed_service
+            .get_feed_by_name("new", now, now, Tz::UTC)
+            .await;
+        assert!(!matches!(result, Err(FeedError::NotFound(_))));
+
+        let result = admin.delete_feed(Actor::Admin, "base").await;
+        assert!(matches!(result, Err(AdminError::ReadOnly(_))));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn users_see_only_issues_of_own_feeds() {
+        let (admin, path) = service("issues");
+
+        let result = admin
+            .add_calendar(Actor::User("alice"), "a", calendar("ftp://a"))
+            .await;
+        let Err(AdminError::Invalid(err)) = result else {
+            panic!("Expected invalid config, got {:?}", result);
+        };
+        let paths: Vec<_> = err.0.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(paths, ["feeds[1].calendars[1].url"]);
+
+        // renaming breaks include of admin's feed
+        let rename = || FeedUpdate {
+            name: Some("c".to_string()),
+            calendars: None,
+            include: None,
+        };
+        let result = admin.update_feed(Actor::User("alice"), "a", rename()).await;
+        assert!(matches!(result, Err(AdminError::Conflict)));
+        let result = admin.update_feed(Actor::Admin, "a", rename()).await;
+        assert!(matches!(result, Err(AdminError::Invalid(_))));
+
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn users_manage_only_own_feeds() {
+        let (admin, path) = service("isolation");
+        let alice = Actor::User("alice");
+
+        let names: Vec<_> = admin
+            .list_feeds(alice)
+            .await
+            .into_iter()
+            .map(|feed| feed.name)
+            .collect();
+        assert_eq!(names, ["a"]);
+
+        let update = FeedUpdate {
+            name: None,
+            calendars: Some(vec![]),
+            include: None,
+        };
+        let result = admin.update_feed(alice, "b", update).await;
+        assert!(matches!(result, Err(AdminError::FeedNotFound(_))));
+        let result = admin.add_calendar(alice, "b", calendar("http://c")).await;
+        assert!(matches!(result, Err(AdminError::FeedNotFound(_))));
+        let result = admin.rotate_token(alice, "b", "private").await;
+        assert!(matches!(result, Err(AdminError::FeedNotFound(_))));
+        let result = admin.delete_feed(alice, "b").await;
+        assert!(matches!(result, Err(AdminError::FeedNotFound(_))));
+        let result = admin.delete_feed(alice, "team").await;
+        assert!(matches!(result, Err(AdminError::FeedNotFound(_))));
+        assert_eq!(admin.list_feeds(Actor::User("bob")).await.len(), 1);
+
+        let new: NewFeed = serde_json::from_str(r#"{"name": "new", "owner": "bob"}"#).unwrap();
+        admin.create_feed(alice, new).await.unwrap();
+        let feeds = admin.list_feeds(Actor::Admin).await;
+        let created = feeds.iter().find(|feed| feed.name == "new").unwrap();
+        assert_eq!(created.owner.as_deref(), Some("alice"));
+        assert_eq!(admin.list_feeds(Actor::User("bob")).await.len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
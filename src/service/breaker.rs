@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests are allowed
+    Closed,
+
+    /// Requests are rejected until open duration passes
+    Open,
+
+    /// Single trial request is allowed to check whether upstream recovered
+    HalfOpen,
+}
+
+/// Circuit breaker stops requests to consistently failing calendar
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    state: BreakerState,
+    failures: u32,
+    threshold: u32,
+    open_duration: Duration,
+    changed_at: Instant,
+    last_success: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            state: BreakerState::Closed,
+            failures: 0,
+            threshold,
+            open_duration,
+            changed_at: Instant::now(),
+            last_success: None,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Number of consecutive failed requests
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Time of last successful request
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        self.last_success
+    }
+
+    /// Time left until next trial request is allowed
+    pub fn retry_in(&self) -> Option<Duration> {
+        match self.state {
+            BreakerState::Closed => None,
+            _ => Some(self.open_duration.saturating_sub(self.changed_at.elapsed())),
+        }
+    }
+
+    /// Returns true if request is allowed.
+    /// Open breaker becomes half-open after open duration and allows single request
+    pub fn try_acquire(&mut self) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            // trial request may be cancelled without reporting result,
+            // so allow another trial after open duration
+            BreakerState::Open | BreakerState::HalfOpen => {
+                if self.changed_at.elapsed() >= self.open_duration {
+                    self.set_state(BreakerState::HalfOpen);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn on_success(&mut self) {
+        self.failures = 0;
+        self.last_success = Some(Utc::now());
+        if self.state != BreakerState::Closed {
+            self.set_state(BreakerState::Closed);
+        }
+    }
+
+    pub fn on_failure(&mut self) {
+        self.failures += 1;
+        if self.state == BreakerState::HalfOpen || self.failures >= self.threshold {
+            self.set_state(BreakerState::Open);
+        }
+    }
+
+    fn set_state(&mut self, state: BreakerState) {
+        self.state = state;
+        self.changed_at = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::service::breaker::{BreakerState, CircuitBreaker};
+
+    #[test]
+    fn opens_after_threshold() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        assert!(breaker.try_acquire());
+        breaker.on_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.try_acquire());
+        breaker.on_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.on_failure();
+        breaker.on_success();
+        breaker.on_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.failures(), 1);
+    }
+
+    #[test]
+    fn half_open_after_duration() {
+        let mut breaker = CircuitBreaker::new(1, Duration::ZERO);
+
+        breaker.on_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        breaker.on_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.try_acquire());
+        breaker.on_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+}
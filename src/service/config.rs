@@ -1,9 +1,15 @@
+use std::collections::HashSet;
 use std::env;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock, RwLock};
 
+use chrono::{DateTime, Utc};
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::service::tokens::{ConfigToken, Privacy, TokenEntry, TokenIndex};
 
 const DEFAULT_CONFIG_FILE: &str = "config";
 
@@ -11,30 +17,244 @@ const DEFAULT_CONFIG_FILE: &str = "config";
 pub struct AppConfig {
     pub server: ServerConfig,
 
-    /// All feeds
+    /// Settings of calendar cache
+    pub cache: CacheConfig,
+
+    /// Settings of calendar downloads
+    pub fetch: FetchConfig,
+
+    /// Admin endpoints are disabled when not set
+    pub admin: Option<AdminConfig>,
+
+    /// Users that can log in and manage their feeds
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+
+    /// Feeds defined in config. Feeds created with admin API are added to them
+    #[serde(default)]
     pub feeds: Vec<FeedConfig>,
+
+    /// Feeds by digests of their tokens, built once after loading
+    #[serde(skip)]
+    token_index: OnceLock<TokenIndex>,
 }
 
 impl AppConfig {
-    pub fn get_feed_by_token(&self, token: &str) -> Option<&FeedConfig> {
-        //todo use hashmap
-        self.feeds.iter().find(|feed| {
-            feed.tokens.private.expose_secret() == token
-                || feed.tokens.public.expose_secret() == token
+    /// Finds feed and token config of given token.
+    /// Revoked and expired tokens are rejected
+    fn find_token(&self, token: &str) -> Option<TokenEntry> {
+        let entry = self.token_index().get(token)?;
+        let feed = &self.feeds[entry.feed];
+        let token = &feed.tokens[entry.token];
+
+        if token.revoked {
+            tracing::info!(
+                "Revoked token '{}' of feed '{}' is used",
+                token.label,
+                feed.name
+            );
+            return None;
+        }
+        if token.is_expired(Utc::now()) {
+            tracing::info!(
+                "Expired token '{}' of feed '{}' is used",
+                token.label,
+                feed.name
+            );
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Names of changed settings which are read only at startup, so the change
+    /// takes effect after restart
+    pub fn restart_changes(&self, new: &AppConfig) -> Vec<&'static str> {
+        let changes = [
+            ("server.host", self.server.host != new.server.host),
+            ("server.port", self.server.port != new.server.port),
+            (
+                "server.session_ttl",
+                self.server.session_ttl != new.server.session_ttl,
+            ),
+            (
+                "cache.directory",
+                self.cache.directory != new.cache.directory,
+            ),
+            // breakers of known calendars keep their settings
+            (
+                "fetch.breaker_threshold",
+                self.fetch.breaker_threshold != new.fetch.breaker_threshold,
+            ),
+            (
+                "fetch.breaker_duration",
+                self.fetch.breaker_duration != new.fetch.breaker_duration,
+            ),
+        ];
+
+        changes
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Checks admin token in constant time
+    pub fn is_admin_token(&self, token: &str) -> bool {
+        self.admin.as_ref().is_some_and(|admin| {
+            ConfigToken::parse(admin.token.expose_secret()).is_ok_and(|t| t.matches(token))
         })
     }
 
-    pub fn load() -> Result<Self, config::ConfigError> {
+    pub fn find_feed(&self, name: &str) -> Option<&FeedConfig> {
+        self.feeds.iter().find(|feed| feed.name == name)
+    }
+
+    /// Returns own calendars of feed followed by calendars of included feeds.
+    /// Calendar used several times is returned once with the most detailed privacy
+    pub fn feed_calendars<'a>(&'a self, feed: &'a FeedConfig) -> Vec<FeedCalendar<'a>> {
+        let mut calendars = vec![];
+        self.collect_calendars(feed, &mut HashSet::new(), &mut calendars);
+        calendars
+    }
+
+    fn collect_calendars<'a>(
+        &'a self,
+        feed: &'a FeedConfig,
+        visited: &mut HashSet<&'a str>,
+        calendars: &mut Vec<FeedCalendar<'a>>,
+    ) {
+        // cycles are rejected by validation, but each feed is still visited once
+        if !visited.insert(&feed.name) {
+            return;
+        }
+
+        for calendar in &feed.calendars {
+            let privacy = calendar.privacy.unwrap_or(Privacy::Private);
+            match calendars.iter_mut().find(|c| c.calendar == calendar) {
+                Some(existing) if privacy == Privacy::Private => existing.privacy = privacy,
+                Some(_) => {}
+                None => calendars.push(FeedCalendar { calendar, privacy }),
+            }
+        }
+        for name in &feed.include {
+            if let Some(included) = self.find_feed(name) {
+                self.collect_calendars(included, visited, calendars);
+            }
+        }
+    }
+
+    pub fn find_user(&self, name: &str) -> Option<&UserConfig> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    fn token_index(&self) -> &TokenIndex {
+        self.token_index.get_or_init(|| {
+            let mut index = TokenIndex::default();
+            for (i, feed) in self.feeds.iter().enumerate() {
+                for (j, token) in feed.tokens.iter().enumerate() {
+                    let entry = TokenEntry { feed: i, token: j };
+                    index.insert(token.token.expose_secret(), entry);
+                }
+            }
+            index
+        })
+    }
+
+    /// Returns copy of config with given feeds
+    pub fn with_feeds(&self, feeds: Vec<FeedConfig>) -> Self {
+        let config = Self {
+            server: self.server.clone(),
+            cache: self.cache.clone(),
+            fetch: self.fetch.clone(),
+            admin: self.admin.clone(),
+            users: self.users.clone(),
+            feeds,
+            token_index: OnceLock::new(),
+        };
+        config.token_index();
+        config
+    }
+
+    /// Reads and validates config. Warnings are logged, errors are returned
+    pub fn load() -> anyhow::Result<Self> {
+        let config = Self::read()?;
+        config.validate().into_result()?;
+        Ok(config)
+    }
+
+    /// Reads config without validation
+    pub fn read() -> Result<Self, config::ConfigError> {
         let config_file = env::var("APP_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_FILE.into());
 
-        config::Config::builder()
+        let config: Self = config::Config::builder()
             .add_source(config::File::with_name("config-default"))
             .add_source(config::File::with_name(&config_file).required(false))
             // Add in settings from environment variables (with a prefix of APP and '_' as separator)
             // E.g. `APP_SERVER_PORT=5001 would set `AppConfig.server.port`
             .add_source(config::Environment::with_prefix("APP").separator("_"))
             .build()?
-            .try_deserialize()
+            .try_deserialize()?;
+        config.token_index();
+        Ok(config)
+    }
+}
+
+/// Calendar shown in feed with the most detailed privacy allowed for it
+#[derive(Clone, Copy, Debug)]
+pub struct FeedCalendar<'a> {
+    pub calendar: &'a CalendarConfig,
+    pub privacy: Privacy,
+}
+
+/// Feed resolved from token. Keeps config snapshot it was resolved with
+#[derive(Clone, Debug)]
+pub struct FeedAccess {
+    config: Arc<AppConfig>,
+    entry: TokenEntry,
+}
+
+impl FeedAccess {
+    /// Returns None if token is unknown, revoked or expired
+    pub fn resolve(config: Arc<AppConfig>, token: &str) -> Option<Self> {
+        let entry = config.find_token(token)?;
+        Some(Self { config, entry })
+    }
+
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    pub fn feed(&self) -> &FeedConfig {
+        &self.config.feeds[self.entry.feed]
+    }
+
+    pub fn token(&self) -> &TokenConfig {
+        &self.feed().tokens[self.entry.token]
+    }
+
+    pub fn privacy(&self) -> Privacy {
+        self.token().privacy
+    }
+}
+
+/// Config which can be replaced while app is running.
+/// Readers get a snapshot, so they finish their work with config they started with
+#[derive(Clone, Debug)]
+pub struct SharedConfig(Arc<RwLock<Arc<AppConfig>>>);
+
+impl SharedConfig {
+    pub fn new(config: AppConfig) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(config))))
+    }
+
+    /// Returns current config
+    pub fn get(&self) -> Arc<AppConfig> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces config and returns previous one
+    pub fn replace(&self, config: AppConfig) -> Arc<AppConfig> {
+        std::mem::replace(&mut *self.0.write().unwrap(), Arc::new(config))
     }
 }
 
@@ -45,33 +265,196 @@ pub struct ServerConfig {
 
     /// Port on which app should listen to
     pub port: u16,
+
+    /// Time (in seconds) after which users have to log in again
+    pub session_ttl: u64,
+
+    /// Session cookies are sent by browsers only over https when set.
+    /// Can be disabled when app is served over plain http
+    pub secure_cookies: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CacheConfig {
+    /// Interval (in seconds) between background refreshes of each calendar
+    pub refresh_interval: u64,
+
+    /// Maximum random delay (in seconds) added to each refresh interval
+    pub refresh_jitter: u64,
+
+    /// Time (in seconds) for which downloaded calendar is kept in cache
+    pub ttl: u64,
+
+    /// Directory where last downloaded calendars are stored between restarts.
+    /// Calendars are kept only in memory when not set
+    pub directory: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FetchConfig {
+    /// Timeout (in seconds) for establishing connection to calendar server
+    pub connect_timeout: u64,
+
+    /// Timeout (in seconds) for receiving next part of response from calendar server
+    pub read_timeout: u64,
+
+    /// Maximum size (in bytes) of downloaded calendar
+    pub max_size: u64,
+
+    /// Number of additional attempts after failed download
+    pub retries: u32,
+
+    /// Delay (in seconds) before first retry, doubled on each next retry
+    pub backoff: u64,
+
+    /// Maximum delay (in seconds) between retries
+    pub max_backoff: u64,
+
+    /// Number of consecutive failed downloads after which calendar is not requested
+    pub breaker_threshold: u32,
+
+    /// Time (in seconds) during which failing calendar is not requested
+    pub breaker_duration: u64,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+pub struct AdminConfig {
+    /// Token to access admin endpoints.
+    /// Either plain token or its digest as `sha256:<hex>`
+    pub token: Secret<String>,
+
+    /// File where feeds managed with admin API are stored.
+    /// Feeds can't be changed at runtime when not set
+    pub store: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserConfig {
+    pub name: String,
+
+    /// Argon2 hash of password in PHC format
+    pub password: Secret<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FeedConfig {
     /// Name of this feed
     pub name: String,
 
+    /// User who manages this feed. Only admin can manage feeds without owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
     /// Tokens to access this feed
-    pub tokens: TokensConfig,
+    #[serde(deserialize_with = "deserialize_tokens")]
+    pub tokens: Vec<TokenConfig>,
 
-    /// Port on which app should listen to
+    /// Calendars which events are shown in this feed
+    #[serde(default)]
     pub calendars: Vec<CalendarConfig>,
+
+    /// Names of other feeds which calendars are shown in this feed as well
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct TokensConfig {
-    /// Token to access all information from calendar
-    pub private: Secret<String>,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenConfig {
+    /// Name of token holder, shown in logs
+    pub label: String,
+
+    /// Either plain token or its digest as `sha256:<hex>`
+    #[serde(serialize_with = "serialize_secret")]
+    pub token: Secret<String>,
 
-    /// Token to access free-busy information from calendar
-    pub public: Secret<String>,
+    /// Information available with this token
+    pub privacy: Privacy,
+
+    /// Token is rejected after this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Revoked token is rejected
+    #[serde(default)]
+    pub revoked: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl TokenConfig {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Tokens are either list of tokens or pair of private and public token
+fn deserialize_tokens<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<TokenConfig>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tokens {
+        List(Vec<TokenConfig>),
+        Pair {
+            private: Secret<String>,
+            public: Secret<String>,
+        },
+    }
+
+    let token = |label: &str, token, privacy| TokenConfig {
+        label: label.to_string(),
+        token,
+        privacy,
+        expires_at: None,
+        revoked: false,
+    };
+
+    Ok(match Tokens::deserialize(deserializer)? {
+        Tokens::List(tokens) => tokens,
+        Tokens::Pair { private, public } => vec![
+            token("private", private, Privacy::Private),
+            token("public", public, Privacy::Public),
+        ],
+    })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CalendarConfig {
     /// Url of ical calendar
+    #[serde(serialize_with = "serialize_secret")]
     pub url: Secret<String>,
+
+    /// Name of calendar shown in diagnostics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Interval (in seconds) between background refreshes of this calendar.
+    /// Global interval is used when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<u64>,
+
+    /// Overrides global cache ttl for this calendar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+
+    /// Overrides global connect timeout for this calendar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+
+    /// Overrides global read timeout for this calendar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+
+    /// Overrides global maximum size for this calendar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+
+    /// Overrides global number of retries for this calendar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+
+    /// Limits details shown from this calendar, e.g. `public` shows only busy time
+    /// even with private token. Token privacy is used when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<Privacy>,
 }
 
 impl PartialEq for CalendarConfig {
@@ -82,8 +465,167 @@ impl PartialEq for CalendarConfig {
 
 impl Eq for CalendarConfig {}
 
+impl CalendarConfig {
+    /// Returns true if all settings (not only url) are the same
+    pub fn same_settings(&self, other: &Self) -> bool {
+        self == other
+            && self.name == other.name
+            && self.refresh_interval == other.refresh_interval
+            && self.ttl == other.ttl
+            && self.connect_timeout == other.connect_timeout
+            && self.read_timeout == other.read_timeout
+            && self.max_size == other.max_size
+            && self.retries == other.retries
+            && self.privacy == other.privacy
+    }
+
+    /// Hash of url, which can be shown or used as file name as url contains secrets
+    pub fn url_digest(&self) -> String {
+        format!("{:x}", Sha256::digest(self.url.expose_secret()))
+    }
+
+    /// Identifies calendar in logs: its name or digest of url when it has no name
+    pub fn log_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.url_digest())
+    }
+}
+
 impl Hash for CalendarConfig {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.url.expose_secret().hash(state)
     }
 }
+
+/// Secrets are serialized only when config is stored, they are never shown in responses
+fn serialize_secret<S: Serializer>(
+    secret: &Secret<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose_secret())
+}
+
+/// Builds configs for tests: default settings with given admin, users and feeds
+#[cfg(test)]
+#[derive(Default)]
+pub struct ConfigBuilder {
+    admin: Option<String>,
+    users: Vec<String>,
+    /// Yaml list of feeds
+    feeds: String,
+}
+
+#[cfg(test)]
+impl ConfigBuilder {
+    /// Argon2 hash given to all users, it never matches any password
+    const PASSWORD: &'static str =
+        "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA";
+
+    pub fn admin(mut self, token: &str) -> Self {
+        self.admin = Some(token.to_string());
+        self
+    }
+
+    pub fn user(mut self, name: &str) -> Self {
+        self.users.push(name.to_string());
+        self
+    }
+
+    /// Adds feed with private and public token and calendars with given urls
+    pub fn feed(self, name: &str, private: &str, public: &str, urls: &[&str]) -> Self {
+        self.feeds(&FeedBuilder::new(name, private, public, urls).yaml())
+    }
+
+    /// Adds feeds given as yaml list items
+    pub fn feeds(mut self, yaml: &str) -> Self {
+        self.feeds.push_str(yaml);
+        self
+    }
+
+    pub fn build(self) -> AppConfig {
+        let mut yaml = include_str!("../../config-default.yml").to_string();
+        if let Some(token) = self.admin {
+            yaml.push_str(&format!("\nadmin:\n  token: {}\n", token));
+        }
+        if !self.users.is_empty() {
+            yaml.push_str("\nusers:\n");
+            for name in self.users {
+                yaml.push_str(&format!(
+                    "  - name: {}\n    password: '{}'\n",
+                    name,
+                    Self::PASSWORD
+                ));
+            }
+        }
+        if !self.feeds.is_empty() {
+            yaml.push_str("\nfeeds:\n");
+            yaml.push_str(&self.feeds);
+        }
+        serde_yaml::from_str(&yaml).expect("Invalid test config")
+    }
+}
+
+/// Builds feeds for tests, either as yaml list item for [`ConfigBuilder::feeds`] or as config
+#[cfg(test)]
+pub struct FeedBuilder {
+    name: String,
+    owner: Option<String>,
+    private: String,
+    public: String,
+    include: Vec<String>,
+    urls: Vec<String>,
+}
+
+#[cfg(test)]
+impl FeedBuilder {
+    /// Feed with private and public token and calendars with given urls
+    pub fn new(name: &str, private: &str, public: &str, urls: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            owner: None,
+            private: private.to_string(),
+            public: public.to_string(),
+            include: vec![],
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+        }
+    }
+
+    pub fn owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn include(mut self, names: &[&str]) -> Self {
+        self.include = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Yaml list item of feed, calendars are the last, so their settings can be appended
+    pub fn yaml(&self) -> String {
+        let mut yaml = format!("  - name: {}\n", self.name);
+        if let Some(owner) = &self.owner {
+            yaml.push_str(&format!("    owner: {}\n", owner));
+        }
+        yaml.push_str(&format!(
+            "    tokens:\n      private: {}\n      public: {}\n",
+            self.private, self.public
+        ));
+        if !self.include.is_empty() {
+            yaml.push_str(&format!("    include: [{}]\n", self.include.join(", ")));
+        }
+        if self.urls.is_empty() {
+            yaml.push_str("    calendars: []\n");
+        } else {
+            yaml.push_str("    calendars:\n");
+            for url in &self.urls {
+                yaml.push_str(&format!("      - url: {}\n", url));
+            }
+        }
+        yaml
+    }
+
+    pub fn build(&self) -> FeedConfig {
+        let mut feeds: Vec<FeedConfig> =
+            serde_yaml::from_str(&self.yaml()).expect("Invalid test feed");
+        feeds.remove(0)
+    }
+}
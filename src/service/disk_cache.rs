@@ -0,0 +1,65 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::CalendarConfig;
+use crate::service::fetch::Validators;
+
+/// Last successfully downloaded calendar
+#[derive(Clone, Debug)]
+pub struct StoredCalendar {
+    pub body: Vec<u8>,
+    pub validators: Validators,
+}
+
+/// Stores last downloaded body of each calendar on disk, so it survives restarts
+#[derive(Clone, Debug)]
+pub struct DiskCache {
+    directory: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    pub async fn load(&self, calendar: &CalendarConfig) -> Option<StoredCalendar> {
+        let (body_path, validators_path) = self.paths(calendar);
+
+        let body = tokio::fs::read(body_path).await.ok()?;
+        let validators = tokio::fs::read(validators_path)
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Some(StoredCalendar { body, validators })
+    }
+
+    pub async fn store(
+        &self,
+        calendar: &CalendarConfig,
+        stored: &StoredCalendar,
+    ) -> io::Result<()> {
+        let (body_path, validators_path) = self.paths(calendar);
+
+        // write to temporary files first, so partially written calendar is never loaded
+        let body_tmp = body_path.with_extension("ics.tmp");
+        let validators_tmp = validators_path.with_extension("json.tmp");
+        tokio::fs::write(&body_tmp, &stored.body).await?;
+        tokio::fs::write(&validators_tmp, serde_json::to_vec(&stored.validators)?).await?;
+        tokio::fs::rename(body_tmp, body_path).await?;
+        tokio::fs::rename(validators_tmp, validators_path).await
+    }
+
+    fn paths(&self, calendar: &CalendarConfig) -> (PathBuf, PathBuf) {
+        // url contains secrets, so only its hash is used as file name
+        let name = calendar.url_digest();
+
+        (
+            self.directory.join(format!("{}.ics", name)),
+            self.directory.join(format!("{}.json", name)),
+        )
+    }
+}
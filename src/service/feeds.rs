@@ -1,157 +1,1324 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use futures::future;
-use ical::parser::ical::component::IcalCalendar;
+use ical::parser::ical::component::{IcalEvent, IcalTimeZone};
+use ical::property::Property;
 use moka::future::Cache;
-use reqwest::Client;
+use moka::Expiry;
+use rand::Rng;
+use rrule::RRule;
 use secrecy::ExposeSecret;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinHandle;
 
-use crate::config::CalendarConfig;
-use crate::model::{CalendarEvent, EventSet, PrimitiveEvent, Timezone};
-use crate::service::config::AppConfig;
+use crate::config::{CalendarConfig, FeedAccess, FeedConfig};
+use crate::model::{iana_local_to_utc, CalendarEvent, EventSet, PrimitiveEvent, Timezone};
+use crate::service::breaker::{BreakerState, CircuitBreaker};
+use crate::service::config::{AppConfig, SharedConfig};
+use crate::service::disk_cache::{DiskCache, StoredCalendar};
+use crate::service::fetch::{self, FetchError, FetchLimits, Fetched, Validators};
+use crate::service::tokens::Privacy;
 
 #[derive(Clone)]
 pub struct FeedService {
-    config: AppConfig,
-    cache: Arc<Cache<CalendarConfig, Vec<u8>>>,
+    config: SharedConfig,
+    cache: Arc<Cache<CalendarConfig, CachedCalendar>>,
+    breakers: Arc<Mutex<HashMap<CalendarConfig, CircuitBreaker>>>,
+    disk_cache: Option<DiskCache>,
+    /// Shared by all downloads, so connections are pooled
+    client: reqwest::Client,
+    refresh_tasks: Arc<Mutex<HashMap<CalendarConfig, JoinHandle<()>>>>,
 }
 
-impl FeedService {
-    pub fn new(config: &AppConfig) -> Self {
+#[derive(Debug, thiserror::Error)]
+pub enum FeedError {
+    #[error("All calendars of feed are unavailable")]
+    Unavailable(#[source] anyhow::Error),
+
+    #[error("Feed '{0}' not found")]
+    NotFound(String),
+}
+
+/// Events of feed in requested range
+#[derive(Debug)]
+pub struct Feed {
+    pub events: Vec<FeedEvent>,
+    /// Problems of calendars which events are missing (fully or partially)
+    pub diagnostics: Vec<CalendarDiagnostics>,
+}
+
+impl Feed {
+    /// Number of calendars that failed to load, so all their events are missing
+    pub fn failed_calendars(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.error.is_some())
+            .count()
+    }
+}
+
+/// Event of feed, shown once even when it is in several calendars
+#[derive(Debug)]
+pub struct FeedEvent {
+    pub event: PrimitiveEvent,
+    /// Calendars of feed with this event
+    pub sources: Vec<EventSource>,
+}
+
+/// Calendar from feed in which event is found
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct EventSource {
+    /// Index of calendar in [`AppConfig::feed_calendars`](crate::config::AppConfig::feed_calendars) of feed:
+    /// own calendars of feed followed by calendars of included feeds
+    pub calendar: usize,
+    /// Name of calendar, hidden for public tokens
+    pub name: Option<String>,
+}
+
+/// Event found in single calendar of feed, before duplicates are merged
+struct SourcedEvent {
+    event: PrimitiveEvent,
+    source: EventSource,
+    is_public: bool,
+}
+
+impl SourcedEvent {
+    /// Copies from private calendars keep summary, so they are preferred to public ones
+    fn detail(&self) -> (bool, usize) {
+        (!self.is_public, self.event.summary.len())
+    }
+}
+
+/// Problems of single calendar from feed
+#[derive(Clone, Debug, Serialize)]
+pub struct CalendarDiagnostics {
+    /// Index of calendar in [`AppConfig::feed_calendars`](crate::config::AppConfig::feed_calendars) of feed:
+    /// own calendars of feed followed by calendars of included feeds
+    pub calendar: usize,
+    /// Name of calendar, hidden for public tokens
+    pub name: Option<String>,
+    /// Set when calendar failed to load
+    pub error: Option<CalendarErrorKind>,
+    /// Number of events that failed to convert and are missing from calendar
+    pub skipped_events: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarErrorKind {
+    /// Calendar server didn't respond in time
+    Timeout,
+    /// Calendar is larger than allowed
+    TooLarge,
+    /// Calendar server responded with error status
+    UpstreamStatus,
+    /// Calendar is failing and is not requested for some time
+    CircuitOpen,
+    /// Network or other error during download
+    Network,
+    /// Calendar server responded with something that is not a calendar
+    InvalidCalendar,
+    /// Error not related to download
+    Internal,
+}
+
+impl CalendarErrorKind {
+    fn new(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<FetchError>() {
+            Some(FetchError::ConnectTimeout(_) | FetchError::ReadTimeout(_)) => Self::Timeout,
+            Some(FetchError::TooLarge(_)) => Self::TooLarge,
+            Some(FetchError::Status(_)) => Self::UpstreamStatus,
+            Some(FetchError::CircuitOpen(_)) => Self::CircuitOpen,
+            Some(FetchError::Request(_)) => Self::Network,
+            Some(FetchError::InvalidCalendar) => Self::InvalidCalendar,
+            None => Self::Internal,
+        }
+    }
+}
+
+/// Download status of single calendar from feed
+#[derive(Clone, Debug, Serialize)]
+pub struct CalendarStatus {
+    pub feed: String,
+    /// Index of calendar in feed config
+    pub calendar: usize,
+    pub state: BreakerState,
+    pub failures: u32,
+    /// Seconds until next download attempt is allowed
+    pub retry_in: Option<u64>,
+    /// Time of last successful download since start
+    pub last_fetched: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+struct CachedCalendar {
+    calendar: Arc<ParsedCalendar>,
+    /// Validators of downloaded body, so refreshes can be conditional
+    validators: Validators,
+    ttl: Duration,
+}
+
+/// Calendar with all events converted to event sets, ready for range expansion
+#[derive(Debug, Default)]
+pub(crate) struct ParsedCalendar {
+    event_sets: Vec<EventSet>,
+    /// Timezones built from VTIMEZONE components, by their definitions.
+    /// They rarely change, so they are reused when changed calendar is parsed again
+    timezones: HashMap<String, Arc<Timezone>>,
+    /// Number of VCALENDAR components
+    pub(crate) calendars: usize,
+    /// Number of VEVENT components
+    pub(crate) events: usize,
+    /// Number of events that failed to convert
+    skipped_events: usize,
+    /// Problems that cause events to be skipped or shown incorrectly
+    pub(crate) issues: Vec<ParseIssue>,
+}
+
+/// Problem found while parsing calendar
+#[derive(Clone, Debug, Serialize)]
+pub struct ParseIssue {
+    pub kind: ParseIssueKind,
+    /// UID of event (or TZID of timezone) with the issue
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl ParseIssue {
+    pub(crate) fn new(kind: ParseIssueKind, id: Option<&str>, message: impl Into<String>) -> Self {
         Self {
-            config: config.clone(),
-            cache: Arc::new(
-                Cache::builder()
-                    .time_to_live(Duration::from_secs(60))
-                    .build(),
-            ),
+            kind,
+            id: id.map(|id| id.to_string()),
+            message: message.into(),
         }
     }
+}
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseIssueKind {
+    /// Calendar can't be parsed at all
+    InvalidCalendar,
+    /// VTIMEZONE can't be parsed, IANA timezone with the same id is used instead
+    InvalidTimezone,
+    /// TZID is neither defined in calendar nor known IANA timezone
+    UnknownTimezone,
+    /// Event can't be converted and is not shown
+    SkippedEvent,
+    /// RRULE can't be parsed, event is shown without recurrence
+    InvalidRrule,
+    /// Override (event with RECURRENCE-ID) doesn't replace any occurrence
+    OrphanOverride,
+}
+
+impl ParsedCalendar {
+    /// Parses calendar, reusing unchanged timezones of its previous version.
+    /// Problems are collected in `issues` instead of failing whole calendar
+    pub(crate) fn parse(bytes: &[u8], previous: Option<&ParsedCalendar>) -> Self {
+        let mut parsed = ParsedCalendar::default();
+        for calendar in ical::IcalParser::new(bytes) {
+            let mut calendar = match calendar {
+                Ok(calendar) => calendar,
+                Err(err) => {
+                    parsed.add_issue(ParseIssueKind::InvalidCalendar, None, err.to_string());
+                    // parser can't recover after error
+                    break;
+                }
+            };
+            parsed.calendars += 1;
+            parsed.events += calendar.events.len();
+
+            let timezones = parsed.add_timezones(
+                std::mem::take(&mut calendar.timezones),
+                previous.map(|p| &p.timezones),
+            );
+            parsed.add_events(calendar.events, &timezones);
+        }
+        parsed
+    }
+
+    pub(crate) fn event_sets(&self) -> &[EventSet] {
+        &self.event_sets
+    }
+
+    pub(crate) fn create_primitives(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: &Tz,
+    ) -> Vec<PrimitiveEvent> {
+        self.event_sets
+            .iter()
+            .flat_map(|set| set.create_primitives(start, end, tz))
+            .collect()
+    }
+
+    /// Builds timezones of calendar and returns them by ids. Timezones with the same
+    /// definition as in previous version of calendar are reused
+    fn add_timezones(
+        &mut self,
+        timezones: Vec<IcalTimeZone>,
+        previous: Option<&HashMap<String, Arc<Timezone>>>,
+    ) -> HashMap<String, Arc<Timezone>> {
+        let mut by_id = HashMap::new();
+        for cal_tz in timezones {
+            // debug output contains all properties of component, so it identifies definition
+            let definition = format!("{:?}", cal_tz);
+            let timezone = match previous.and_then(|previous| previous.get(&definition)) {
+                Some(timezone) => timezone.clone(),
+                None => {
+                    let tzid = find_property(&cal_tz.properties, "TZID");
+                    match Timezone::try_from(cal_tz) {
+                        Ok(timezone) => Arc::new(timezone),
+                        Err(err) => {
+                            let message = format!("{:#}", err);
+                            self.add_issue(
+                                ParseIssueKind::InvalidTimezone,
+                                tzid.as_deref(),
+                                message,
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.timezones.insert(definition, timezone.clone());
+            by_id.insert(timezone.id().to_string(), timezone);
+        }
+        by_id
+    }
+
+    /// Converts events and groups them by UID into event sets
+    fn add_events(&mut self, events: Vec<IcalEvent>, timezones: &HashMap<String, Arc<Timezone>>) {
+        let mut grouped: HashMap<_, Vec<CalendarEvent>> = HashMap::new();
+        for event in events {
+            if let Some(event) = self.convert_event(event, timezones) {
+                grouped.entry(event.uid.clone()).or_default().push(event);
+            }
+        }
+
+        for (uid, events) in grouped {
+            self.add_event_set(uid, events);
+        }
+    }
+
+    fn convert_event(
+        &mut self,
+        event: IcalEvent,
+        timezones: &HashMap<String, Arc<Timezone>>,
+    ) -> Option<CalendarEvent> {
+        let uid = find_property(&event.properties, "UID");
+        let rrule = find_property(&event.properties, "RRULE");
+
+        let unknown_tzids = RefCell::new(BTreeSet::new());
+        let result = CalendarEvent::from_ical_event(event, |tzid, datetime| {
+            // conversion fails only when timezone is unknown
+            local_to_utc(timezones, tzid, datetime).inspect_err(|_| {
+                unknown_tzids.borrow_mut().insert(tzid.to_string());
+            })
+        });
+
+        for tzid in unknown_tzids.into_inner() {
+            let message = format!("Unknown timezone: {}", tzid);
+            self.add_issue(ParseIssueKind::UnknownTimezone, uid.as_deref(), message);
+        }
+
+        let event = match result {
+            Ok(event) => event,
+            Err(err) => {
+                let message = format!("{:#}", err);
+                self.add_issue(ParseIssueKind::SkippedEvent, uid.as_deref(), message);
+                self.skipped_events += 1;
+                return None;
+            }
+        };
+
+        if let Some(rrule) = rrule {
+            if event.recurrence.is_none() {
+                let message = match RRule::from_str(&rrule) {
+                    Err(err) => format!("Failed to parse '{}': {}", rrule, err),
+                    Ok(_) => format!("'{}' is not valid for event start", rrule),
+                };
+                self.add_issue(ParseIssueKind::InvalidRrule, uid.as_deref(), message);
+            }
+        }
+
+        Some(event)
+    }
+
+    fn add_event_set(&mut self, uid: String, events: Vec<CalendarEvent>) {
+        let count = events.len();
+        let master = events.iter().find(|e| e.recurrence_id.is_none());
+        let orphan_reason = match master {
+            None => Some("no event without RECURRENCE-ID has the same UID"),
+            Some(master) if master.recurrence.is_none() => {
+                Some("event with the same UID is not recurring")
+            }
+            Some(_) => None,
+        };
+        if let Some(reason) = orphan_reason {
+            for event in events.iter().filter(|e| e.recurrence_id.is_some()) {
+                let message = format!(
+                    "Override of {:?} is invalid: {}",
+                    event.recurrence_id.unwrap(),
+                    reason
+                );
+                self.add_issue(ParseIssueKind::OrphanOverride, Some(&uid), message);
+            }
+        }
+
+        match EventSet::new(uid.clone(), events) {
+            Ok(set) => self.event_sets.push(set),
+            Err(err) => {
+                let message = format!("{} event(s) with this UID are skipped: {:#}", count, err);
+                self.add_issue(ParseIssueKind::SkippedEvent, Some(&uid), message);
+                self.skipped_events += count;
+            }
+        }
+    }
+
+    fn add_issue(&mut self, kind: ParseIssueKind, id: Option<&str>, message: impl Into<String>) {
+        self.issues.push(ParseIssue::new(kind, id, message));
+    }
+}
+
+/// Expires each cached calendar after its own ttl
+struct CalendarExpiry;
+
+impl Expiry<CalendarConfig, CachedCalendar> for CalendarExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &CalendarConfig,
+        value: &CachedCalendar,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(value.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &CalendarConfig,
+        value: &CachedCalendar,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(value.ttl)
+    }
+}
+
+impl FeedService {
+    pub fn new(config: SharedConfig) -> anyhow::Result<Self> {
+        let disk_cache = config
+            .get()
+            .cache
+            .directory
+            .as_ref()
+            .map(DiskCache::new)
+            .transpose()
+            .context("Failed to create cache directory")?;
+
+        Ok(Self {
+            config,
+            cache: Arc::new(Cache::builder().expire_after(CalendarExpiry).build()),
+            breakers: Default::default(),
+            disk_cache,
+            client: fetch::client()?,
+            refresh_tasks: Default::default(),
+        })
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Loads calendars stored on disk into cache, so they are available
+    /// before first download completes
+    pub async fn load_disk_cache(&self) {
+        let Some(disk_cache) = &self.disk_cache else {
+            return;
+        };
+
+        let config = self.config.get();
+        for calendar in distinct_calendars(&config) {
+            if let Some(stored) = disk_cache.load(calendar).await {
+                if let Err(err) = self.parse_calendar(calendar, stored, None).await {
+                    tracing::error!("Failed to load stored calendar: {:?}", err);
+                }
+            }
+        }
+    }
+
+    /// Returns circuit breaker status of each calendar in each feed
+    pub fn calendar_statuses(&self) -> Vec<CalendarStatus> {
+        let breakers = self.breakers.lock().unwrap();
+
+        self.config
+            .get()
+            .feeds
+            .iter()
+            .flat_map(|feed| {
+                feed.calendars
+                    .iter()
+                    .enumerate()
+                    .map(|(i, calendar)| {
+                        let breaker = breakers.get(calendar);
+                        CalendarStatus {
+                            feed: feed.name.clone(),
+                            calendar: i,
+                            state: breaker.map_or(BreakerState::Closed, |b| b.state()),
+                            failures: breaker.map_or(0, |b| b.failures()),
+                            retry_in: breaker.and_then(|b| b.retry_in()).map(|d| d.as_secs()),
+                            last_fetched: breaker.and_then(|b| b.last_success()),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Replaces config of service. Cache is kept for calendars which urls didn't change
+    /// and refresh tasks are restarted only for added or changed calendars
+    pub async fn reload(&self, config: AppConfig) {
+        let old = self.config.replace(config);
+        let new = self.config.get();
+
+        for setting in old.restart_changes(&new) {
+            tracing::warn!("Change of {} is applied only after restart", setting);
+        }
+
+        let calendars = distinct_calendars(&new);
+        for calendar in distinct_calendars(&old) {
+            if !calendars.contains(calendar) {
+                self.cache.invalidate(calendar).await;
+                self.breakers.lock().unwrap().remove(calendar);
+            }
+        }
+
+        self.start_refresh_tasks();
+    }
+
+    /// Starts background task for each distinct calendar that periodically
+    /// downloads it, so requests are served from warm cache.
+    /// Tasks of removed or changed calendars are stopped
+    pub fn start_refresh_tasks(&self) {
+        let config = self.config.get();
+        let calendars = distinct_calendars(&config);
+        let mut tasks = self.refresh_tasks.lock().unwrap();
+
+        tasks.retain(|calendar, task| {
+            let keep = calendars
+                .get(calendar)
+                .is_some_and(|c| c.same_settings(calendar));
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+
+        for calendar in calendars {
+            if tasks.contains_key(calendar) {
+                continue;
+            }
+
+            let service = self.clone();
+            let task_calendar = calendar.clone();
+            let task = tokio::spawn(async move {
+                let calendar = task_calendar;
+                loop {
+                    if let Err(err) = service.download_calendar(&calendar).await {
+                        tracing::error!("Failed to refresh calendar: {:?}", err);
+                    }
+
+                    // global settings may change on reload, so they are read each time
+                    let config = service.config.get();
+                    let interval = refresh_interval(&config, &calendar);
+                    let jitter = config.cache.refresh_jitter;
+                    let jitter = Duration::from_secs(rand::thread_rng().gen_range(0..=jitter));
+                    tokio::time::sleep(interval + jitter).await;
+                }
+            });
+            tasks.insert(calendar.clone(), task);
+        }
+    }
+
+    pub fn stop_refresh_tasks(&self) {
+        for (_, task) in self.refresh_tasks.lock().unwrap().drain() {
+            task.abort();
+        }
+    }
+
+    /// Returns events of feed in given range.
+    /// All day events are selected by dates in given timezone
     pub async fn get_feed(
         &self,
-        token: &str,
+        access: &FeedAccess,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: Tz,
+    ) -> Result<Feed, FeedError> {
+        let feed = access.feed();
+        tracing::info!(
+            "Feed '{}' accessed with token '{}'",
+            feed.name,
+            access.token().label
+        );
+        self.collect_feed(access.config(), feed, access.privacy(), start, end, tz)
+            .await
+    }
+
+    /// Returns events of several feeds fetched in parallel, in the same order as accesses
+    pub async fn get_feeds(
+        &self,
+        accesses: &[FeedAccess],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: Tz,
+    ) -> Vec<Result<Feed, FeedError>> {
+        let feeds = accesses
+            .iter()
+            .map(|access| self.get_feed(access, start, end, tz));
+        future::join_all(feeds).await
+    }
+
+    /// Returns all information of feed with given name in given range
+    pub async fn get_feed_by_name(
+        &self,
+        name: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: Tz,
+    ) -> Result<Feed, FeedError> {
+        let config = self.config.get();
+        let feed = config
+            .find_feed(name)
+            .ok_or_else(|| FeedError::NotFound(name.to_string()))?;
+
+        self.collect_feed(&config, feed, Privacy::Private, start, end, tz)
+            .await
+    }
+
+    /// Collects events of own and included calendars of feed.
+    /// Each calendar is shown with privacy of token restricted by calendar settings
+    async fn collect_feed(
+        &self,
+        config: &AppConfig,
+        feed: &FeedConfig,
+        privacy: Privacy,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> anyhow::Result<Vec<PrimitiveEvent>> {
-        let config = self
-            .config
-            .get_feed_by_token(token)
-            .context("Invalid token")?;
-        let is_public = token == config.tokens.public.expose_secret();
-
-        let events_futures: Vec<_> = config
-            .calendars
+        tz: Tz,
+    ) -> Result<Feed, FeedError> {
+        let calendars = config.feed_calendars(feed);
+        let events_futures: Vec<_> = calendars
             .iter()
-            .map(|calendar| self.fetch_calendar_events(calendar, start, end))
+            .map(|c| self.fetch_calendar_events(c.calendar, start, end, tz))
             .collect();
 
-        let events = future::join_all(events_futures)
+        let mut errors = vec![];
+        let mut diagnostics = vec![];
+        let events: Vec<_> = future::join_all(events_futures)
             .await
             .into_iter()
-            .filter_map(|res| {
-                if let Err(err) = res {
-                    tracing::error!("Failed to fetch calendar: {:?}", err);
-                    None
-                } else {
-                    res.ok()
-                }
-            })
-            .flatten()
-            .map(|event| {
-                if is_public {
-                    PrimitiveEvent {
-                        range: event.range,
-                        summary: "Busy".to_string(),
+            .zip(&calendars)
+            .enumerate()
+            .filter_map(|(i, (res, calendar))| {
+                let is_public = privacy.restrict(calendar.privacy) == Privacy::Public;
+                let name = calendar.calendar.name.clone().filter(|_| !is_public);
+                match res {
+                    Ok((events, skipped_events)) => {
+                        if skipped_events > 0 {
+                            diagnostics.push(CalendarDiagnostics {
+                                calendar: i,
+                                name: name.clone(),
+                                error: None,
+                                skipped_events,
+                            });
+                        }
+                        let source = EventSource { calendar: i, name };
+                        Some(events.into_iter().map(move |event| SourcedEvent {
+                            event,
+                            source: source.clone(),
+                            is_public,
+                        }))
+                    }
+                    Err(err) => {
+                        tracing::error!("Failed to fetch calendar: {:?}", err);
+                        diagnostics.push(CalendarDiagnostics {
+                            calendar: i,
+                            name,
+                            error: Some(CalendarErrorKind::new(&err)),
+                            skipped_events: 0,
+                        });
+                        errors.push(err);
+                        None
                     }
-                } else {
-                    event
                 }
             })
+            .flatten()
             .collect();
 
-        Ok(events)
+        if !errors.is_empty() && errors.len() == calendars.len() {
+            return Err(FeedError::Unavailable(errors.swap_remove(0)));
+        }
+
+        Ok(Feed {
+            events: merge_duplicates(events),
+            diagnostics,
+        })
     }
 
+    /// Returns events of calendar in given range with number of skipped events
     async fn fetch_calendar_events(
         &self,
         calendar: &CalendarConfig,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> anyhow::Result<Vec<PrimitiveEvent>> {
-        let client = Client::builder().build().unwrap();
-
+        tz: Tz,
+    ) -> anyhow::Result<(Vec<PrimitiveEvent>, usize)> {
         let cached = self.cache.get(calendar).await;
 
-        let bytes = if let Some(cached) = cached {
-            tracing::info!("Using cached events: {:?}", cached.len());
-            cached
+        let parsed = if let Some(cached) = cached {
+            tracing::info!(
+                "Using cached events: {:?}",
+                cached.calendar.event_sets.len()
+            );
+            cached.calendar
         } else {
-            let response = client
-                .get(calendar.url.expose_secret())
-                .send()
-                .await
-                .context("Failed to get calendar from url")?;
-            let bytes = response
-                .bytes()
+            self.download_calendar(calendar).await?
+        };
+
+        Ok((
+            parsed.create_primitives(start, end, &tz),
+            parsed.skipped_events,
+        ))
+    }
+
+    /// Downloads and parses calendar and stores it in cache.
+    /// Calendar that is not modified is kept in cache without parsing it again
+    async fn download_calendar(
+        &self,
+        calendar: &CalendarConfig,
+    ) -> anyhow::Result<Arc<ParsedCalendar>> {
+        let cached = self.cache.get(calendar).await;
+        // stored copy is only needed when calendar is not in memory, e.g. after restart
+        let stored = match (&cached, &self.disk_cache) {
+            (None, Some(disk_cache)) => disk_cache.load(calendar).await,
+            _ => None,
+        };
+
+        let result = match self.acquire_breaker(calendar) {
+            Ok(()) => {
+                let limits = FetchLimits::new(&self.config.get().fetch, calendar);
+                let validators = match (&cached, &stored) {
+                    (Some(cached), _) => Some(&cached.validators),
+                    (None, stored) => stored.as_ref().map(|stored| &stored.validators),
+                };
+                let result = match fetch::fetch(
+                    &self.client,
+                    calendar.url.expose_secret(),
+                    &limits,
+                    validators,
+                )
                 .await
-                .context("Failed to get calendar bytes")?;
-            let bytes = bytes.to_vec();
-            tracing::info!("Downloaded ical: {}", bytes.len());
-            self.cache.insert(calendar.clone(), bytes.clone()).await;
-            bytes
+                {
+                    // body replaces last good copy only when it is a calendar
+                    Ok(Fetched::Modified { body, validators }) => {
+                        let previous = cached.as_ref().map(|cached| cached.calendar.clone());
+                        parse_downloaded(body, previous)
+                            .await
+                            .map(|(parsed, body)| {
+                                Downloaded::Modified(parsed, StoredCalendar { body, validators })
+                            })
+                    }
+                    Ok(Fetched::NotModified) => Ok(Downloaded::NotModified),
+                    Err(err) => Err(err.into()),
+                };
+                self.update_breaker(calendar, result.is_ok());
+                result
+            }
+            Err(err) => Err(err.into()),
         };
 
-        let reader = ical::IcalParser::new(bytes.as_ref());
+        match (result, cached, stored) {
+            (Ok(Downloaded::Modified(parsed, stored)), _, _) => {
+                tracing::info!("Downloaded ical: {}", stored.body.len());
+                if let Some(disk_cache) = &self.disk_cache {
+                    if let Err(err) = disk_cache.store(calendar, &stored).await {
+                        tracing::error!("Failed to store calendar on disk: {:?}", err);
+                    }
+                }
+                Ok(self
+                    .cache_calendar(calendar, parsed, stored.validators)
+                    .await)
+            }
+            (Ok(Downloaded::NotModified), Some(cached), _) => {
+                tracing::info!("Calendar not modified, keeping parsed copy");
+                Ok(self.keep_calendar(calendar, cached).await)
+            }
+            // last good copy is served during outages only when it is persisted
+            (Err(err), Some(cached), _) if self.disk_cache.is_some() => {
+                tracing::warn!("Failed to download calendar, keeping last copy: {:#}", err);
+                Ok(self.keep_calendar(calendar, cached).await)
+            }
+            (Ok(Downloaded::NotModified), None, Some(stored)) => {
+                tracing::info!("Calendar not modified, using stored copy");
+                self.parse_calendar(calendar, stored, None).await
+            }
+            (Err(err), None, Some(stored)) => {
+                tracing::warn!("Failed to download calendar, using stored copy: {:#}", err);
+                self.parse_calendar(calendar, stored, None).await
+            }
+            (Ok(Downloaded::NotModified), _, _) => {
+                anyhow::bail!("Calendar not modified, but stored copy is missing")
+            }
+            (Err(err), _, _) => Err(err),
+        }
+    }
+
+    /// Parses calendar and stores it in cache.
+    /// Unchanged timezones of previous version are reused
+    async fn parse_calendar(
+        &self,
+        calendar: &CalendarConfig,
+        stored: StoredCalendar,
+        previous: Option<Arc<ParsedCalendar>>,
+    ) -> anyhow::Result<Arc<ParsedCalendar>> {
+        let StoredCalendar { body, validators } = stored;
+        let (parsed, _) = parse_blocking(body, previous).await?;
+        Ok(self.cache_calendar(calendar, parsed, validators).await)
+    }
 
-        let mut calendar_events = vec![];
-        for calendar in reader.flatten() {
-            let mut new_events = create_events(calendar, start, end);
-            calendar_events.append(&mut new_events);
+    async fn cache_calendar(
+        &self,
+        calendar: &CalendarConfig,
+        parsed: ParsedCalendar,
+        validators: Validators,
+    ) -> Arc<ParsedCalendar> {
+        let parsed = Arc::new(parsed);
+        let cached = CachedCalendar {
+            calendar: parsed.clone(),
+            validators,
+            ttl: self.calendar_ttl(calendar),
+        };
+        self.cache.insert(calendar.clone(), cached).await;
+        parsed
+    }
+
+    /// Stores calendar in cache again, so it expires only after fresh ttl
+    async fn keep_calendar(
+        &self,
+        calendar: &CalendarConfig,
+        cached: CachedCalendar,
+    ) -> Arc<ParsedCalendar> {
+        let parsed = cached.calendar.clone();
+        let cached = CachedCalendar {
+            ttl: self.calendar_ttl(calendar),
+            ..cached
+        };
+        self.cache.insert(calendar.clone(), cached).await;
+        parsed
+    }
+
+    fn calendar_ttl(&self, calendar: &CalendarConfig) -> Duration {
+        Duration::from_secs(calendar.ttl.unwrap_or(self.config.get().cache.ttl))
+    }
+
+    fn acquire_breaker(&self, calendar: &CalendarConfig) -> Result<(), FetchError> {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(calendar.clone())
+            .or_insert_with(|| self.create_breaker());
+
+        let was_open = breaker.state() == BreakerState::Open;
+        if breaker.try_acquire() {
+            if was_open {
+                tracing::info!(
+                    "Circuit breaker of {} is half-open, trying to download calendar",
+                    calendar.log_name()
+                );
+            }
+            Ok(())
+        } else {
+            Err(FetchError::CircuitOpen(
+                breaker.retry_in().unwrap_or_default(),
+            ))
+        }
+    }
+
+    fn update_breaker(&self, calendar: &CalendarConfig, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(calendar.clone())
+            .or_insert_with(|| self.create_breaker());
+
+        let old_state = breaker.state();
+        if success {
+            breaker.on_success();
+        } else {
+            breaker.on_failure();
         }
-        Ok(calendar_events)
+
+        if breaker.state() != old_state {
+            match breaker.state() {
+                BreakerState::Open => tracing::warn!(
+                    "Circuit breaker of {} opened after {} failures, next attempt in {:?}",
+                    calendar.log_name(),
+                    breaker.failures(),
+                    breaker.retry_in().unwrap_or_default()
+                ),
+                BreakerState::Closed => {
+                    tracing::info!("Circuit breaker of {} closed", calendar.log_name())
+                }
+                BreakerState::HalfOpen => {}
+            }
+        }
+    }
+
+    fn create_breaker(&self) -> CircuitBreaker {
+        let config = self.config.get();
+        CircuitBreaker::new(
+            config.fetch.breaker_threshold,
+            Duration::from_secs(config.fetch.breaker_duration),
+        )
     }
 }
 
-fn create_events(
-    calendar: IcalCalendar,
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
-) -> Vec<PrimitiveEvent> {
-    let timezones: HashMap<_, _> = calendar
-        .timezones
-        .into_iter()
-        .filter_map(|cal_tz| Timezone::try_from(cal_tz).ok())
-        .map(|tz| (tz.id().to_string(), tz))
-        .collect();
+/// Result of download which body is already parsed
+enum Downloaded {
+    Modified(ParsedCalendar, StoredCalendar),
+    NotModified,
+}
 
-    let mut events: HashMap<_, Vec<CalendarEvent>> = HashMap::new();
+/// Parses body off async runtime and returns it back, so it can be stored
+async fn parse_blocking(
+    body: Vec<u8>,
+    previous: Option<Arc<ParsedCalendar>>,
+) -> anyhow::Result<(ParsedCalendar, Vec<u8>)> {
+    let (parsed, body) = tokio::task::spawn_blocking(move || {
+        let parsed = ParsedCalendar::parse(&body, previous.as_deref());
+        (parsed, body)
+    })
+    .await
+    .context("Failed to parse calendar")?;
+    for issue in &parsed.issues {
+        tracing::warn!("Calendar issue: {:?}", issue);
+    }
+    Ok((parsed, body))
+}
 
-    calendar
-        .events
-        .into_iter()
-        .filter_map::<CalendarEvent, _>(|e| {
-            CalendarEvent::from_ical_event(e, |tz, time| {
-                timezones.get(tz).unwrap().local_to_utc(time)
+/// Parses downloaded body. Bodies that aren't calendars (e.g. login or error pages
+/// sent with status 200) are rejected, so they don't replace last good copy
+async fn parse_downloaded(
+    body: Vec<u8>,
+    previous: Option<Arc<ParsedCalendar>>,
+) -> anyhow::Result<(ParsedCalendar, Vec<u8>)> {
+    let (parsed, body) = parse_blocking(body, previous).await?;
+    let invalid = parsed
+        .issues
+        .iter()
+        .any(|issue| issue.kind == ParseIssueKind::InvalidCalendar);
+    if parsed.calendars == 0 || invalid {
+        return Err(FetchError::InvalidCalendar.into());
+    }
+    Ok((parsed, body))
+}
+
+/// Merges copies of the same event from different calendars (e.g. a person and a room).
+/// Copies are matched by uid and occurrence, or by summary and time when uids differ.
+/// Events of one calendar with different uids are never merged, even when they look the same.
+/// The most detailed copy is kept and events of public calendars are hidden after that
+fn merge_duplicates(events: Vec<SourcedEvent>) -> Vec<FeedEvent> {
+    let mut merged: Vec<(SourcedEvent, Vec<EventSource>)> = vec![];
+    let mut by_uid: HashMap<_, usize> = HashMap::new();
+    let mut by_content: HashMap<_, usize> = HashMap::new();
+
+    for event in events {
+        let uid_key = (event.event.uid.clone(), event.event.instance);
+        let content_key = (event.event.summary.clone(), event.event.range.clone());
+        let found = by_uid.get(&uid_key).copied().or_else(|| {
+            by_content.get(&content_key).copied().filter(|&index| {
+                let (_, sources) = &merged[index];
+                sources
+                    .iter()
+                    .all(|source| source.calendar != event.source.calendar)
             })
-            .map_err(|e| tracing::error!("Failed to convert: {:?}", e))
-            .ok()
-        })
-        .for_each(|event| {
-            events.entry(event.uid.clone()).or_default().push(event);
         });
 
-    events
+        let index = match found {
+            Some(index) => {
+                let (kept, sources) = &mut merged[index];
+                if !sources.contains(&event.source) {
+                    sources.push(event.source.clone());
+                }
+                if event.detail() > kept.detail() {
+                    *kept = event;
+                }
+                index
+            }
+            None => {
+                let sources = vec![event.source.clone()];
+                merged.push((event, sources));
+                merged.len() - 1
+            }
+        };
+        by_uid.entry(uid_key).or_insert(index);
+        by_content.entry(content_key).or_insert(index);
+    }
+
+    merged
         .into_iter()
-        .filter_map(|(id, events)| {
-            EventSet::new(id, events)
-                .map_err(|e| tracing::error!("Failed to create event set: {:?}", e))
-                .ok()
+        .map(|(kept, sources)| FeedEvent {
+            event: if kept.is_public {
+                busy_event(kept.event)
+            } else {
+                kept.event
+            },
+            sources,
         })
-        .flat_map(|set| set.create_primitives(start, end))
         .collect()
 }
+
+/// Hides everything but time of event.
+/// Uid is hashed, so exported events can still be tracked without exposing it
+fn busy_event(event: PrimitiveEvent) -> PrimitiveEvent {
+    PrimitiveEvent {
+        summary: "Busy".to_string(),
+        uid: format!("{:x}", Sha256::digest(event.uid.as_bytes())),
+        ..event
+    }
+}
+
+/// Returns each calendar of all feeds once
+fn distinct_calendars(config: &AppConfig) -> HashSet<&CalendarConfig> {
+    config
+        .feeds
+        .iter()
+        .flat_map(|feed| &feed.calendars)
+        .collect()
+}
+
+fn refresh_interval(config: &AppConfig, calendar: &CalendarConfig) -> Duration {
+    Duration::from_secs(
+        calendar
+            .refresh_interval
+            .unwrap_or(config.cache.refresh_interval),
+    )
+}
+
+fn find_property(properties: &[Property], name: &str) -> Option<String> {
+    properties
+        .iter()
+        .find(|p| p.name == name)
+        .and_then(|p| p.value.clone())
+}
+
+/// Converts local datetime using timezone from calendar.
+/// When timezone is missing or broken, falls back to IANA timezone with the same id
+fn local_to_utc(
+    timezones: &HashMap<String, Arc<Timezone>>,
+    tzid: &str,
+    datetime: NaiveDateTime,
+) -> anyhow::Result<DateTime<Utc>> {
+    if let Some(timezone) = timezones.get(tzid) {
+        return Ok(timezone.local_to_utc(datetime));
+    }
+
+    let tz: Tz = tzid
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Unknown timezone: {}", tzid))?;
+    Ok(iana_local_to_utc(tz, datetime))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use secrecy::ExposeSecret;
+
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use crate::config::{AppConfig, ConfigBuilder, SharedConfig};
+    use crate::service::feeds::{
+        busy_event, merge_duplicates, CachedCalendar, EventSource, FeedService, ParsedCalendar,
+        SourcedEvent,
+    };
+    use crate::service::tokens::Privacy;
+
+    fn config(urls: &[&str]) -> AppConfig {
+        ConfigBuilder::default()
+            .feed("test", "a", "b", urls)
+            .build()
+    }
+
+    fn calendar_urls(service: &FeedService) -> Vec<String> {
+        let mut urls: Vec<_> = service
+            .refresh_tasks
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|c| c.url.expose_secret().clone())
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_unchanged_calendars() {
+        let config = config(&["http://127.0.0.1:9/a", "http://127.0.0.1:9/b"]);
+        let calendar_b = config.feeds[0].calendars[1].clone();
+        let service = FeedService::new(SharedConfig::new(config)).unwrap();
+        service.start_refresh_tasks();
+
+        let cached = CachedCalendar {
+            calendar: Arc::new(Default::default()),
+            validators: Default::default(),
+            ttl: Duration::from_secs(600),
+        };
+        service.cache.insert(calendar_b.clone(), cached).await;
+
+        service
+            .reload(self::config(&[
+                "http://127.0.0.1:9/b",
+                "http://127.0.0.1:9/c",
+            ]))
+            .await;
+
+        assert_eq!(
+            calendar_urls(&service),
+            vec!["http://127.0.0.1:9/b", "http://127.0.0.1:9/c"]
+        );
+        assert!(service.cache.get(&calendar_b).await.is_some());
+        service.stop_refresh_tasks();
+    }
+
+    #[test]
+    fn restart_only_changes_are_reported() {
+        let old = config(&[]);
+        let mut new = config(&["http://a"]);
+        new.server.port += 1;
+        new.fetch.breaker_threshold += 1;
+        new.fetch.retries += 1;
+
+        assert_eq!(
+            old.restart_changes(&new),
+            ["server.port", "fetch.breaker_threshold"]
+        );
+    }
+
+    #[test]
+    fn included_calendars_are_shown_once() {
+        let config = ConfigBuilder::default()
+            .feeds("  - name: a\n    tokens: []\n    calendars:\n      - url: http://a\n        privacy: public\n      - url: http://b\n")
+            .feeds("  - name: b\n    tokens: []\n    include: [a]\n    calendars:\n      - url: http://b\n        privacy: public\n")
+            .feeds("  - name: team\n    tokens: []\n    include: [a, b]\n")
+            .build();
+
+        let calendars: Vec<_> = config
+            .feed_calendars(config.find_feed("team").unwrap())
+            .into_iter()
+            .map(|c| (c.calendar.url.expose_secret().as_str(), c.privacy))
+            .collect();
+
+        assert_eq!(
+            calendars,
+            vec![
+                ("http://a", Privacy::Public),
+                ("http://b", Privacy::Private)
+            ]
+        );
+    }
+
+    fn sourced_events(calendar: usize, is_public: bool, ics: &str) -> Vec<SourcedEvent> {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+        let ics = format!("BEGIN:VCALENDAR\nVERSION:2.0\n{}END:VCALENDAR\n", ics);
+
+        let mut events =
+            ParsedCalendar::parse(ics.as_bytes(), None).create_primitives(start, end, &Tz::UTC);
+        events.sort_by_key(|e| e.range.start().into_datetime());
+        events
+            .into_iter()
+            .map(|event| SourcedEvent {
+                event,
+                source: EventSource {
+                    calendar,
+                    name: None,
+                },
+                is_public,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_events_are_merged() {
+        let room = sourced_events(
+            0,
+            true,
+            "BEGIN:VEVENT\nUID:standup\nDTSTART:20240304T090000Z\nDTEND:20240304T091500Z\nRRULE:FREQ=DAILY;COUNT=2\nSUMMARY:Standup\nEND:VEVENT\n",
+        );
+        let person = sourced_events(
+            1,
+            false,
+            "BEGIN:VEVENT\nUID:standup\nDTSTART:20240304T090000Z\nDTEND:20240304T091500Z\nRRULE:FREQ=DAILY;COUNT=2\nSUMMARY:Standup\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:standup\nRECURRENCE-ID:20240305T090000Z\nDTSTART:20240305T100000Z\nDTEND:20240305T101500Z\nSUMMARY:Standup (moved)\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:lunch-copy\nDTSTART:20240306T120000Z\nDTEND:20240306T130000Z\nSUMMARY:Lunch\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:other-lunch\nDTSTART:20240306T120000Z\nDTEND:20240306T130000Z\nSUMMARY:Lunch\nEND:VEVENT\n",
+        );
+        let shared = sourced_events(
+            2,
+            false,
+            "BEGIN:VEVENT\nUID:lunch\nDTSTART:20240306T120000Z\nDTEND:20240306T130000Z\nSUMMARY:Lunch\nEND:VEVENT\n",
+        );
+
+        let events: Vec<_> =
+            merge_duplicates([room, person, shared].into_iter().flatten().collect())
+                .into_iter()
+                .map(|e| {
+                    let calendars: Vec<_> = e.sources.iter().map(|s| s.calendar).collect();
+                    (e.event.summary, calendars)
+                })
+                .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                ("Standup".to_string(), vec![0, 1]),
+                ("Standup (moved)".to_string(), vec![0, 1]),
+                ("Lunch".to_string(), vec![1, 2]),
+                // the same calendar has two lunches, so they aren't copies of one event
+                ("Lunch".to_string(), vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_timezones_are_reused() {
+        let calendar = |summary: &str| {
+            format!(
+                "BEGIN:VCALENDAR\nVERSION:2.0\n\
+                 BEGIN:VTIMEZONE\nTZID:Custom\nBEGIN:STANDARD\nDTSTART:19700101T000000\n\
+                 TZOFFSETFROM:+0100\nTZOFFSETTO:+0100\nEND:STANDARD\nEND:VTIMEZONE\n\
+                 BEGIN:VEVENT\nUID:a\nDTSTART;TZID=Custom:20240304T090000\n\
+                 DTEND;TZID=Custom:20240304T100000\nSUMMARY:{}\nEND:VEVENT\nEND:VCALENDAR\n",
+                summary
+            )
+        };
+
+        let first = ParsedCalendar::parse(calendar("First").as_bytes(), None);
+        let second = ParsedCalendar::parse(calendar("Second").as_bytes(), Some(&first));
+
+        let timezone = |parsed: &ParsedCalendar| parsed.timezones.values().next().unwrap().clone();
+        assert_eq!(second.timezones.len(), 1);
+        assert!(Arc::ptr_eq(&timezone(&first), &timezone(&second)));
+    }
+
+    #[tokio::test]
+    async fn not_modified_calendar_is_not_parsed_again() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use axum::http::{header, HeaderMap, StatusCode};
+        use axum::response::IntoResponse;
+
+        static NOT_MODIFIED: AtomicUsize = AtomicUsize::new(0);
+        let app = axum::Router::new().route(
+            "/a.ics",
+            axum::routing::get(|headers: HeaderMap| async move {
+                if headers
+                    .get(header::IF_NONE_MATCH)
+                    .is_some_and(|v| v == "\"v1\"")
+                {
+                    NOT_MODIFIED.fetch_add(1, Ordering::SeqCst);
+                    return StatusCode::NOT_MODIFIED.into_response();
+                }
+                let body = "BEGIN:VCALENDAR\nVERSION:2.0\nEND:VCALENDAR\n";
+                ([(header::ETAG, "\"v1\"")], body).into_response()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/a.ics", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = config(&[&url]);
+        let calendar = config.feeds[0].calendars[0].clone();
+        let service = FeedService::new(SharedConfig::new(config)).unwrap();
+
+        let first = service.download_calendar(&calendar).await.unwrap();
+        let second = service.download_calendar(&calendar).await.unwrap();
+
+        assert_eq!(NOT_MODIFIED.load(Ordering::SeqCst), 1);
+        assert!(Arc::ptr_eq(&first, &second));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn invalid_body_does_not_replace_last_copy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::service::disk_cache::DiskCache;
+
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        let app = axum::Router::new().route(
+            "/a.ics",
+            axum::routing::get(|| async {
+                // login page of captive portal comes with status 200 as well
+                match REQUESTS.fetch_add(1, Ordering::SeqCst) {
+                    0 => "BEGIN:VCALENDAR\nVERSION:2.0\nBEGIN:VEVENT\nUID:a\nDTSTART:20240304T090000Z\nDTEND:20240304T100000Z\nSUMMARY:Standup\nEND:VEVENT\nEND:VCALENDAR\n",
+                    _ => "<html><body>Please log in</body></html>",
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/a.ics", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let directory =
+            std::env::temp_dir().join(format!("icaliada-invalid-{}", std::process::id()));
+        let mut config = config(&[&url]);
+        config.cache.directory = Some(directory.to_string_lossy().to_string());
+        let calendar = config.feeds[0].calendars[0].clone();
+        let service = FeedService::new(SharedConfig::new(config)).unwrap();
+
+        let first = service.download_calendar(&calendar).await.unwrap();
+        let second = service.download_calendar(&calendar).await.unwrap();
+
+        assert_eq!(REQUESTS.load(Ordering::SeqCst), 2);
+        assert!(Arc::ptr_eq(&first, &second));
+        let stored = DiskCache::new(&directory)
+            .unwrap()
+            .load(&calendar)
+            .await
+            .unwrap();
+        assert!(stored.body.starts_with(b"BEGIN:VCALENDAR"));
+        assert_eq!(service.calendar_statuses()[0].failures, 1);
+
+        server.abort();
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn busy_events_hide_summary_and_uid() {
+        let event = sourced_events(
+            0,
+            true,
+            "BEGIN:VEVENT\nUID:secret@example.com\nDTSTART:20240304T090000Z\nDTEND:20240304T100000Z\nSUMMARY:Interview\nEND:VEVENT\n",
+        )
+        .remove(0)
+        .event;
+
+        let busy = busy_event(event.clone());
+
+        assert_eq!(busy.summary, "Busy");
+        assert!(!busy.uid.contains("secret"));
+        assert_eq!(busy.uid, busy_event(event).uid);
+    }
+}
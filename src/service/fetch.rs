@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CalendarConfig, FetchConfig};
+
+/// Limits applied when downloading single calendar
+#[derive(Clone, Copy, Debug)]
+pub struct FetchLimits {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub max_size: u64,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl FetchLimits {
+    /// Creates limits from global config
+    pub fn from_config(config: &FetchConfig) -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(config.connect_timeout),
+            read_timeout: Duration::from_secs(config.read_timeout),
+            max_size: config.max_size,
+            retries: config.retries,
+            backoff: Duration::from_secs(config.backoff),
+            max_backoff: Duration::from_secs(config.max_backoff),
+        }
+    }
+
+    /// Creates limits from global config with overrides from calendar config
+    pub fn new(config: &FetchConfig, calendar: &CalendarConfig) -> Self {
+        let limits = Self::from_config(config);
+        Self {
+            connect_timeout: calendar
+                .connect_timeout
+                .map_or(limits.connect_timeout, Duration::from_secs),
+            read_timeout: calendar
+                .read_timeout
+                .map_or(limits.read_timeout, Duration::from_secs),
+            max_size: calendar.max_size.unwrap_or(limits.max_size),
+            retries: calendar.retries.unwrap_or(limits.retries),
+            ..limits
+        }
+    }
+
+    /// Delay before given retry attempt (starting from 1), doubled on each attempt
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff)
+    }
+}
+
+/// Validators of downloaded calendar used for conditional requests
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Result of successful download
+#[derive(Debug)]
+pub enum Fetched {
+    /// Calendar was changed since validators were received
+    Modified {
+        body: Vec<u8>,
+        validators: Validators,
+    },
+
+    /// Calendar was not changed since validators were received
+    NotModified,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("Failed to connect to calendar within {0:?}")]
+    ConnectTimeout(Duration),
+
+    #[error("No data received from calendar within {0:?}")]
+    ReadTimeout(Duration),
+
+    #[error("Calendar is larger than {0} bytes")]
+    TooLarge(u64),
+
+    #[error("Calendar responded with status {0}")]
+    Status(StatusCode),
+
+    #[error("Failed to download calendar")]
+    Request(#[source] reqwest::Error),
+
+    #[error("Calendar is failing, next attempt in {0:?}")]
+    CircuitOpen(Duration),
+
+    /// Body is not a calendar, e.g. login page of captive portal
+    #[error("Calendar response is not a valid calendar")]
+    InvalidCalendar,
+}
+
+impl FetchError {
+    /// Returns true if error is temporary and download may succeed on retry
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::ConnectTimeout(_) | FetchError::ReadTimeout(_) => true,
+            FetchError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            FetchError::Request(err) => !err.is_builder() && !err.is_decode(),
+            FetchError::TooLarge(_) | FetchError::CircuitOpen(_) | FetchError::InvalidCalendar => {
+                false
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        // url of calendar is a secret, so it must not be shown in errors
+        FetchError::Request(err.without_url())
+    }
+}
+
+/// Creates client shared by downloads, so connections and TLS setup are reused.
+/// Timeouts are applied per request, as calendars may override them
+pub fn client() -> anyhow::Result<Client> {
+    Client::builder()
+        .build()
+        .context("Failed to create http client")
+}
+
+/// Downloads calendar from url, retrying transient failures with exponential backoff.
+/// When validators are given, calendar is downloaded only if it was modified
+pub async fn fetch(
+    client: &Client,
+    url: &str,
+    limits: &FetchLimits,
+    validators: Option<&Validators>,
+) -> Result<Fetched, FetchError> {
+    let mut attempt = 0;
+    loop {
+        match fetch_once(client, url, limits, validators).await {
+            Ok(fetched) => return Ok(fetched),
+            Err(err) if err.is_transient() && attempt < limits.retries => {
+                attempt += 1;
+                let backoff = limits.backoff(attempt);
+                tracing::warn!(
+                    "Failed to fetch calendar (attempt {}), retrying in {:?}: {}",
+                    attempt,
+                    backoff,
+                    err
+                );
+                tokio::time::sleep(backoff).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn fetch_once(
+    client: &Client,
+    url: &str,
+    limits: &FetchLimits,
+    validators: Option<&Validators>,
+) -> Result<Fetched, FetchError> {
+    let mut request = client.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    // response headers must arrive within read timeout after connection is established
+    let mut response =
+        tokio::time::timeout(limits.connect_timeout + limits.read_timeout, request.send())
+            .await
+            .map_err(|_| FetchError::ReadTimeout(limits.read_timeout))?
+            .map_err(|err| {
+                if err.is_connect() && err.is_timeout() {
+                    FetchError::ConnectTimeout(limits.connect_timeout)
+                } else {
+                    FetchError::from(err)
+                }
+            })?;
+
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(Fetched::NotModified);
+    }
+    if !status.is_success() {
+        return Err(FetchError::Status(status));
+    }
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > limits.max_size)
+    {
+        return Err(FetchError::TooLarge(limits.max_size));
+    }
+
+    let mut bytes = vec![];
+    while let Some(chunk) = tokio::time::timeout(limits.read_timeout, response.chunk())
+        .await
+        .map_err(|_| FetchError::ReadTimeout(limits.read_timeout))??
+    {
+        if (bytes.len() + chunk.len()) as u64 > limits.max_size {
+            return Err(FetchError::TooLarge(limits.max_size));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(Fetched::Modified {
+        body: bytes,
+        validators: Validators::from_headers(response.headers()),
+    })
+}
@@ -0,0 +1,161 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+
+use crate::model::{DatePerhapsTime, PrimitiveEvent};
+
+/// Maximum length of content line in octets (without line break)
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Serializes events to iCalendar format.
+/// Each event becomes separate VEVENT since recurrences are already expanded
+pub fn write_calendar(name: &str, events: &[PrimitiveEvent]) -> String {
+    let now = format_datetime(Utc::now());
+
+    let mut out = String::new();
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, "PRODID:-//icaliada//EN");
+    write_line(&mut out, "CALSCALE:GREGORIAN");
+    write_line(&mut out, &format!("X-WR-CALNAME:{}", escape_text(name)));
+
+    for event in events {
+        let (start, end) = event.range.either(
+            |start, end| {
+                (
+                    format!("DTSTART;VALUE=DATE:{}", start.format("%Y%m%d")),
+                    format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+                )
+            },
+            |start, end| {
+                (
+                    format!("DTSTART:{}", format_datetime(start)),
+                    format!("DTEND:{}", format_datetime(end)),
+                )
+            },
+        );
+
+        write_line(&mut out, "BEGIN:VEVENT");
+        // uid must stay the same between exports, so clients can track events.
+        // Occurrences of recurring event share source uid, so start of occurrence is added
+        let uid = format!("{}-{}", event.uid, format_instance(event.instance));
+        write_line(&mut out, &format!("UID:{}", escape_text(&uid)));
+        write_line(&mut out, &format!("DTSTAMP:{}", now));
+        write_line(&mut out, &start);
+        write_line(&mut out, &end);
+        write_line(
+            &mut out,
+            &format!("SUMMARY:{}", escape_text(&event.summary)),
+        );
+        write_line(&mut out, "END:VEVENT");
+    }
+
+    write_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+fn format_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_instance(instance: DatePerhapsTime) -> String {
+    match instance {
+        DatePerhapsTime::Date(date) => date.format("%Y%m%d").to_string(),
+        DatePerhapsTime::DateTime(datetime) => format_datetime(datetime),
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Writes content line folded to lines of at most 75 octets
+fn write_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            out.push_str("\r\n ");
+            // leading space is counted as part of the line
+            length = 1;
+        }
+        out.write_char(c).unwrap();
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use crate::service::feeds::ParsedCalendar;
+    use crate::service::ics::{write_calendar, write_line};
+
+    /// Returns uids of exported events of calendar with given events
+    fn exported_uids(events: &[&str]) -> Vec<String> {
+        let events: String = events
+            .iter()
+            .map(|event| format!("BEGIN:VEVENT\r\nSUMMARY:Test\r\n{}END:VEVENT\r\n", event))
+            .collect();
+        let calendar = format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            events
+        );
+
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+        let mut events = ParsedCalendar::parse(calendar.as_bytes(), None).create_primitives(
+            start,
+            end,
+            &Tz::UTC,
+        );
+        events.sort_by_key(|e| e.range.start().into_datetime());
+
+        write_calendar("Test", &events)
+            .lines()
+            .filter_map(|line| line.strip_prefix("UID:"))
+            .map(|uid| uid.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn uids_do_not_depend_on_other_events() {
+        let standup = "UID:a\r\nDTSTART:20240304T090000Z\r\nDTEND:20240304T091500Z\r\nRRULE:FREQ=DAILY;COUNT=2\r\n";
+        let lunch = "UID:b\r\nDTSTART:20240304T080000Z\r\nDTEND:20240304T083000Z\r\n";
+        let holiday = "UID:c\r\nDTSTART;VALUE=DATE:20240306\r\nDTEND;VALUE=DATE:20240307\r\n";
+
+        let all = exported_uids(&[standup, lunch, holiday]);
+        let without_lunch = exported_uids(&[standup, holiday]);
+
+        assert_eq!(
+            all,
+            vec![
+                "b-20240304T080000Z",
+                "a-20240304T090000Z",
+                "a-20240305T090000Z",
+                "c-20240306"
+            ]
+        );
+        assert_eq!(without_lunch, all[1..]);
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let mut out = String::new();
+        let line = format!("SUMMARY:{}", "ы".repeat(50));
+        write_line(&mut out, &line);
+
+        let lines: Vec<_> = out.trim_end().split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| l.len() <= 75));
+        assert_eq!(
+            lines.iter().map(|l| l.trim_start()).collect::<String>(),
+            line
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::model::PrimitiveEvent;
+
+/// Event as returned to clients
+#[derive(Clone, Debug, Serialize)]
+pub struct EventDto {
+    start: String,
+    end: String,
+    title: String,
+}
+
+impl EventDto {
+    /// Times are formatted with offset of given timezone, or in UTC when not set
+    pub fn new(event: PrimitiveEvent, tz: Option<Tz>) -> Self {
+        let fmt_date = "%Y-%m-%d";
+        let format_datetime = |datetime: DateTime<Utc>| match tz {
+            Some(tz) => datetime
+                .with_timezone(&tz)
+                .format("%Y-%m-%dT%H:%M:%S%:z")
+                .to_string(),
+            None => datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        };
+
+        let (start, end) = event.range.either(
+            |start, end| {
+                (
+                    start.format(fmt_date).to_string(),
+                    end.format(fmt_date).to_string(),
+                )
+            },
+            |start, end| (format_datetime(start), format_datetime(end)),
+        );
+        EventDto {
+            start,
+            end,
+            title: event.summary,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::Duration;
+use chrono_tz::Tz;
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::config::FetchConfig;
+use crate::model::EventSet;
+use crate::service::feeds::{ParseIssue, ParseIssueKind, ParsedCalendar};
+use crate::service::fetch::{self, FetchLimits, Fetched};
+
+/// Result of checking single calendar
+#[derive(Debug, Default, Serialize)]
+pub struct LintReport {
+    /// Number of VCALENDAR components
+    pub calendars: usize,
+    /// Number of VEVENT components
+    pub events: usize,
+    /// Number of event sets (events grouped by UID) that will be shown
+    pub event_sets: usize,
+    pub issues: Vec<ParseIssue>,
+}
+
+impl LintReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Downloads calendar and checks it
+pub async fn lint_url(
+    client: &Client,
+    url: &str,
+    config: &FetchConfig,
+) -> anyhow::Result<LintReport> {
+    let limits = FetchLimits::from_config(config);
+    match fetch::fetch(client, url, &limits, None).await? {
+        Fetched::Modified { body, .. } => Ok(lint_calendar(&body)),
+        Fetched::NotModified => anyhow::bail!("Calendar responded with unexpected status"),
+    }
+}
+
+/// Parses calendar the same way as feeds do and reports every problem
+/// that causes events to be skipped or shown incorrectly
+pub fn lint_calendar(bytes: &[u8]) -> LintReport {
+    let mut parsed = ParsedCalendar::parse(bytes, None);
+
+    let mut report = LintReport {
+        calendars: parsed.calendars,
+        events: parsed.events,
+        event_sets: parsed.event_sets().len(),
+        issues: std::mem::take(&mut parsed.issues),
+    };
+    for set in parsed.event_sets() {
+        lint_overrides(set, &mut report);
+    }
+
+    if report.calendars == 0 && report.issues.is_empty() {
+        report.issues.push(ParseIssue::new(
+            ParseIssueKind::InvalidCalendar,
+            None,
+            "No calendars found",
+        ));
+    }
+
+    report
+}
+
+/// Reports overrides which are ignored since their RECURRENCE-ID doesn't match any occurrence
+fn lint_overrides(set: &EventSet, report: &mut LintReport) {
+    let Some(recurrence) = &set.recurrence else {
+        return;
+    };
+    for event_override in &set.overrides {
+        let id = event_override.recurrence_id.into_datetime();
+        let id_tz = id.with_timezone(&rrule::Tz::Tz(Tz::UTC));
+        let occurrences = recurrence
+            .clone()
+            .after(id_tz - Duration::seconds(1))
+            .before(id_tz + Duration::seconds(1))
+            .all(1);
+        if !occurrences.dates.contains(&id_tz) {
+            report.issues.push(ParseIssue::new(
+                ParseIssueKind::OrphanOverride,
+                Some(&set.uid),
+                format!(
+                    "Override of {:?} doesn't match any occurrence and is ignored",
+                    event_override.recurrence_id
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::service::feeds::ParseIssueKind;
+    use crate::service::lint::lint_calendar;
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:test\r\n{}END:VCALENDAR\r\n",
+            events
+        )
+    }
+
+    fn event(uid: &str, extra: &str) -> String {
+        format!(
+            "BEGIN:VEVENT\r\nUID:{}\r\nSUMMARY:Test\r\n{}END:VEVENT\r\n",
+            uid, extra
+        )
+    }
+
+    #[rstest]
+    #[case::valid(
+        event("a", "DTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\n"),
+        vec![]
+    )]
+    #[case::missing_end(event("a", "DTSTART:20240101T100000Z\r\n"), vec![ParseIssueKind::SkippedEvent])]
+    #[case::unknown_tzid(
+        event("a", "DTSTART;TZID=Mars/Base:20240101T100000\r\nDTEND:20240101T110000Z\r\n"),
+        vec![ParseIssueKind::UnknownTimezone, ParseIssueKind::SkippedEvent]
+    )]
+    #[case::iana_tzid(
+        event("a", "DTSTART;TZID=Europe/Berlin:20240101T100000\r\nDTEND:20240101T110000Z\r\n"),
+        vec![]
+    )]
+    #[case::invalid_rrule(
+        event("a", "DTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\nRRULE:FREQ=SOMETIMES\r\n"),
+        vec![ParseIssueKind::InvalidRrule]
+    )]
+    #[case::override_without_master(
+        event("a", "DTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\nRECURRENCE-ID:20240101T100000Z\r\n"),
+        vec![ParseIssueKind::OrphanOverride, ParseIssueKind::SkippedEvent]
+    )]
+    #[case::override_of_missing_occurrence(
+        event("a", "DTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\nRRULE:FREQ=DAILY;COUNT=3\r\n")
+            + &event("a", "DTSTART:20240102T120000Z\r\nDTEND:20240102T130000Z\r\nRECURRENCE-ID:20240102T100000Z\r\n")
+            + &event("a", "DTSTART:20240105T120000Z\r\nDTEND:20240105T130000Z\r\nRECURRENCE-ID:20240105T100000Z\r\n"),
+        vec![ParseIssueKind::OrphanOverride]
+    )]
+    fn lint_issues(#[case] events: String, #[case] expected: Vec<ParseIssueKind>) {
+        let report = lint_calendar(calendar(&events).as_bytes());
+        let kinds: Vec<_> = report.issues.iter().map(|i| i.kind).collect();
+
+        assert_eq!(kinds, expected, "{:?}", report.issues);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod admin;
+pub mod breaker;
 pub mod config;
+pub mod disk_cache;
 pub mod feeds;
+pub mod fetch;
+pub mod ics;
+pub mod json;
+pub mod lint;
+pub mod range;
+pub mod store;
+pub mod tokens;
+pub mod users;
 pub mod utils;
+pub mod validation;
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::model::iana_local_to_utc;
+
+/// Maximum length of requested range of events
+pub const MAX_RANGE_DAYS: i64 = 400;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RangeError {
+    #[error("Invalid {0} datetime: {1}")]
+    InvalidDatetime(&'static str, String),
+
+    #[error("Unknown timezone: {0}")]
+    UnknownTimezone(String),
+
+    #[error("Start must be before end")]
+    EmptyRange,
+
+    #[error("Range must not be longer than {MAX_RANGE_DAYS} days")]
+    RangeTooLong,
+}
+
+pub fn parse_timezone(tz: &str) -> Result<Tz, RangeError> {
+    tz.parse()
+        .map_err(|_| RangeError::UnknownTimezone(tz.to_string()))
+}
+
+/// Parses and validates range of events.
+/// Dates and datetimes without offset are treated as local time in given timezone
+pub fn parse_range(
+    start: &str,
+    end: &str,
+    tz: Tz,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), RangeError> {
+    let start = parse_datetime(start, tz)
+        .ok_or_else(|| RangeError::InvalidDatetime("start", start.to_string()))?;
+    let end = parse_datetime(end, tz)
+        .ok_or_else(|| RangeError::InvalidDatetime("end", end.to_string()))?;
+
+    if start >= end {
+        return Err(RangeError::EmptyRange);
+    }
+    if end - start > Duration::days(MAX_RANGE_DAYS) {
+        return Err(RangeError::RangeTooLong);
+    }
+
+    Ok((start, end))
+}
+
+/// Parses date, local datetime or datetime with offset
+pub fn parse_datetime(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    // '+' of offset becomes space when query parameter is not encoded
+    let value = value.trim().replace(' ', "+");
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(&value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    let local = NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .ok()?;
+
+    Some(iana_local_to_utc(tz, local))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use chrono_tz::Tz;
+    use rstest::rstest;
+
+    use crate::service::range::{parse_datetime, parse_range, RangeError};
+
+    #[rstest]
+    #[case("2023-06-05T00:00:00Z", "UTC", "2023-06-05T00:00:00Z")]
+    #[case("2023-06-05T00:00:00+03:00", "UTC", "2023-06-04T21:00:00Z")]
+    #[case("2023-06-05T00:00:00 03:00", "UTC", "2023-06-04T21:00:00Z")]
+    #[case("2023-06-05T00:00:00", "Europe/Moscow", "2023-06-04T21:00:00Z")]
+    #[case("2023-06-05T00:00", "Europe/Moscow", "2023-06-04T21:00:00Z")]
+    #[case("2023-06-05", "Europe/Moscow", "2023-06-04T21:00:00Z")]
+    #[case("2023-06-05", "UTC", "2023-06-05T00:00:00Z")]
+    fn test_parse_start(#[case] start: &str, #[case] tz: &str, #[case] expected: &str) {
+        let tz: Tz = tz.parse().unwrap();
+        let expected: DateTime<Utc> = expected.parse().unwrap();
+
+        let (start, _) = parse_range(start, "2023-07-01T00:00:00Z", tz).unwrap();
+
+        assert_eq!(start, expected);
+    }
+
+    /// Local times skipped by transitions are shifted forward by length of the gap
+    #[rstest]
+    #[case::dst_gap("2023-03-26T02:30:00", "Europe/Berlin", "2023-03-26T01:30:00Z")]
+    #[case::half_hour_gap("2023-10-01T02:15:00", "Australia/Lord_Howe", "2023-09-30T15:45:00Z")]
+    #[case::skipped_day("2011-12-30", "Pacific/Apia", "2011-12-30T10:00:00Z")]
+    fn test_parse_skipped_time(#[case] value: &str, #[case] tz: &str, #[case] expected: &str) {
+        let tz: Tz = tz.parse().unwrap();
+        let expected: DateTime<Utc> = expected.parse().unwrap();
+
+        assert_eq!(parse_datetime(value, tz), Some(expected));
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        let tz = Tz::UTC;
+
+        assert!(matches!(
+            parse_range("2023-06-05", "yesterday", tz),
+            Err(RangeError::InvalidDatetime("end", _))
+        ));
+        assert!(matches!(
+            parse_range("2023-06-05", "2023-06-05", tz),
+            Err(RangeError::EmptyRange)
+        ));
+        assert!(matches!(
+            parse_range("2020-01-01", "2023-01-01", tz),
+            Err(RangeError::RangeTooLong)
+        ));
+    }
+}
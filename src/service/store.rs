@@ -0,0 +1,63 @@
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AppConfig, FeedConfig};
+
+/// Keeps feeds created with admin API in local JSON file
+#[derive(Clone, Debug)]
+pub struct FeedStore {
+    path: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct StoredFeeds {
+    feeds: Vec<FeedConfig>,
+}
+
+impl FeedStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns store set in admin config
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        config
+            .admin
+            .as_ref()
+            .and_then(|admin| admin.store.as_ref())
+            .map(Self::new)
+    }
+
+    /// Returns stored feeds. Missing file means nothing was stored yet
+    pub async fn load(&self) -> anyhow::Result<Vec<FeedConfig>> {
+        let bytes = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+
+        let stored: StoredFeeds = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))?;
+        Ok(stored.feeds)
+    }
+
+    pub async fn save(&self, feeds: &[FeedConfig]) -> anyhow::Result<()> {
+        let stored = StoredFeeds {
+            feeds: feeds.to_vec(),
+        };
+
+        // write to temporary file first, so partially written store is never loaded
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(&stored)?)
+            .await
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
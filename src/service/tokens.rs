@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Write};
+
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Length of generated tokens by default
+pub const DEFAULT_TOKEN_LENGTH: usize = 48;
+
+/// Prefix of token stored as hex encoded SHA-256 digest
+pub const SHA256_PREFIX: &str = "sha256:";
+
+pub type TokenDigest = [u8; 32];
+
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum TokenError {
+    #[error("SHA-256 digest must be 64 hex characters")]
+    InvalidDigest,
+}
+
+/// Token as written in config
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConfigToken {
+    pub digest: TokenDigest,
+    /// True if config contains only digest of token
+    pub hashed: bool,
+}
+
+impl ConfigToken {
+    /// Parses token from config: either plain token or `sha256:<hex digest>`
+    pub fn parse(value: &str) -> Result<Self, TokenError> {
+        match value.strip_prefix(SHA256_PREFIX) {
+            Some(hex) => Ok(Self {
+                digest: decode_hex(hex).ok_or(TokenError::InvalidDigest)?,
+                hashed: true,
+            }),
+            None => Ok(Self {
+                digest: digest(value),
+                hashed: false,
+            }),
+        }
+    }
+
+    /// Checks whether given token matches this one in constant time
+    pub fn matches(&self, token: &str) -> bool {
+        constant_time_eq(&self.digest, &digest(token))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Privacy {
+    /// All information from calendars is shown
+    Private,
+
+    /// Only busy hours are shown
+    Public,
+}
+
+impl Privacy {
+    /// Returns privacy that shows less of two
+    pub fn restrict(self, other: Privacy) -> Privacy {
+        if self == Privacy::Public || other == Privacy::Public {
+            Privacy::Public
+        } else {
+            Privacy::Private
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TokenEntry {
+    /// Index of feed in config
+    pub feed: usize,
+    /// Index of token in feed config
+    pub token: usize,
+}
+
+/// Index of all feed tokens by their digests
+#[derive(Clone, Default)]
+pub struct TokenIndex {
+    entries: HashMap<TokenDigest, TokenEntry>,
+}
+
+/// Digests are not shown, so they don't end up in logs
+impl Debug for TokenIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TokenIndex({} tokens)", self.entries.len())
+    }
+}
+
+impl TokenIndex {
+    /// Adds token to index. Invalid and duplicate tokens are reported by config validation
+    pub fn insert(&mut self, token: &str, entry: TokenEntry) {
+        if let Ok(token) = ConfigToken::parse(token) {
+            self.entries.entry(token.digest).or_insert(entry);
+        }
+    }
+
+    pub fn get(&self, token: &str) -> Option<TokenEntry> {
+        let digest = digest(token);
+        // map lookup is not constant time, but attacker controls only the token,
+        // not its digest, so timing reveals nothing useful about stored digests
+        let (stored, entry) = self.entries.get_key_value(&digest)?;
+        constant_time_eq(stored, &digest).then_some(*entry)
+    }
+}
+
+/// Generates random alphanumeric token
+pub fn generate_token(length: usize) -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), length)
+}
+
+pub fn digest(token: &str) -> TokenDigest {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// Returns token in form that can be stored in config instead of plain token
+pub fn hashed_token(token: &str) -> String {
+    let mut hashed = SHA256_PREFIX.to_string();
+    for byte in digest(token) {
+        write!(hashed, "{:02x}", byte).unwrap();
+    }
+    hashed
+}
+
+/// Compares slices without early exit, so time doesn't depend on position of difference
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn decode_hex(hex: &str) -> Option<TokenDigest> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::service::tokens::{hashed_token, ConfigToken, TokenError};
+
+    #[test]
+    fn hashed_token_matches_plain() {
+        let plain = ConfigToken::parse("secret").unwrap();
+        let hashed = ConfigToken::parse(&hashed_token("secret")).unwrap();
+
+        assert!(!plain.hashed);
+        assert!(hashed.hashed);
+        assert_eq!(plain.digest, hashed.digest);
+        assert!(hashed.matches("secret"));
+        assert!(!hashed.matches("secret2"));
+    }
+
+    #[rstest]
+    #[case("sha256:abc")]
+    #[case("sha256:zz5e8848ab1a8a7f1b2a8b6e3e1c5d7c6b8e9f0a1b2c3d4e5f60718293a4b5c6")]
+    fn invalid_digest(#[case] value: &str) {
+        assert_eq!(ConfigToken::parse(value), Err(TokenError::InvalidDigest));
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use moka::future::Cache;
+use secrecy::ExposeSecret;
+use tokio::sync::Semaphore;
+
+use crate::config::SharedConfig;
+use crate::service::tokens::{self, TokenDigest, DEFAULT_TOKEN_LENGTH};
+
+/// Returns argon2 hash of password in PHC format
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow::anyhow!("{}", err))
+        .context("Failed to hash password")?;
+    Ok(hash.to_string())
+}
+
+/// Checks password against hash. Invalid hashes never match
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Password checks running at once. Each takes ~19 MiB of memory and a cpu thread,
+/// so logins beyond this limit fail instead of exhausting the server
+const MAX_PASSWORD_CHECKS: usize = 4;
+
+/// Who is logged in with session
+#[derive(Clone, Debug)]
+enum SessionOwner {
+    Admin {
+        /// Digest of admin token in config at login, sessions end when token is changed
+        token: TokenDigest,
+    },
+    User {
+        name: String,
+        /// Digest of password hash at login, sessions end when password is changed
+        password: TokenDigest,
+    },
+}
+
+/// Logs users and admin in and keeps their sessions in memory, so sessions end on restart
+#[derive(Clone)]
+pub struct UserService {
+    config: SharedConfig,
+    /// Owners of sessions by digests of session ids
+    sessions: Cache<TokenDigest, SessionOwner>,
+    password_checks: Arc<Semaphore>,
+}
+
+impl UserService {
+    pub fn new(config: SharedConfig) -> Self {
+        let ttl = Duration::from_secs(config.get().server.session_ttl);
+        Self {
+            config,
+            sessions: Cache::builder().time_to_live(ttl).build(),
+            password_checks: Arc::new(Semaphore::new(MAX_PASSWORD_CHECKS)),
+        }
+    }
+
+    /// Checks credentials and returns id of new session.
+    /// Login fails when too many passwords are being checked
+    pub async fn login(&self, name: &str, password: &str) -> Option<String> {
+        let Ok(permit) = self.password_checks.clone().try_acquire_owned() else {
+            tracing::warn!(
+                "Too many logins at once, rejecting login of user '{}'",
+                name
+            );
+            return None;
+        };
+
+        let config = self.config.get();
+        let hash = match config.find_user(name) {
+            Some(user) => user.password.expose_secret().clone(),
+            // unknown users take as long as known ones, so they can't be told apart
+            None => dummy_hash().to_string(),
+        };
+
+        let password = password.to_string();
+        let fingerprint = tokens::digest(&hash);
+        let valid = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            verify_password(&hash, &password)
+        })
+        .await
+        .unwrap_or(false);
+        if !valid || config.find_user(name).is_none() {
+            tracing::info!("Failed login of user '{}'", name);
+            return None;
+        }
+
+        tracing::info!("User '{}' logged in", name);
+        let owner = SessionOwner::User {
+            name: name.to_string(),
+            password: fingerprint,
+        };
+        Some(self.start(owner).await)
+    }
+
+    /// Checks admin token and returns id of new admin session
+    pub async fn admin_login(&self, token: &str) -> Option<String> {
+        let config = self.config.get();
+        let admin = config
+            .admin
+            .as_ref()
+            .filter(|_| config.is_admin_token(token));
+        let Some(admin) = admin else {
+            tracing::info!("Failed login of admin");
+            return None;
+        };
+        tracing::info!("Admin logged in");
+        let owner = SessionOwner::Admin {
+            token: tokens::digest(admin.token.expose_secret()),
+        };
+        Some(self.start(owner).await)
+    }
+
+    /// Returns name of user logged in with given session.
+    /// Sessions of users removed from config or with changed password are rejected
+    pub async fn user(&self, session: &str) -> Option<String> {
+        let SessionOwner::User { name, password } =
+            self.sessions.get(&tokens::digest(session)).await?
+        else {
+            return None;
+        };
+        self.config
+            .get()
+            .find_user(&name)
+            .filter(|user| tokens::digest(user.password.expose_secret()) == password)
+            .map(|user| user.name.clone())
+    }
+
+    /// Checks that admin is logged in with given session.
+    /// Sessions are rejected once admin token is changed or removed from config
+    pub async fn is_admin(&self, session: &str) -> bool {
+        let Some(SessionOwner::Admin { token }) = self.sessions.get(&tokens::digest(session)).await
+        else {
+            return false;
+        };
+        self.config
+            .get()
+            .admin
+            .as_ref()
+            .is_some_and(|admin| tokens::digest(admin.token.expose_secret()) == token)
+    }
+
+    pub async fn logout(&self, session: &str) {
+        self.sessions.invalidate(&tokens::digest(session)).await;
+    }
+
+    async fn start(&self, owner: SessionOwner) -> String {
+        let session = tokens::generate_token(DEFAULT_TOKEN_LENGTH);
+        self.sessions.insert(tokens::digest(&session), owner).await;
+        session
+    }
+}
+
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("dummy").expect("Failed to hash dummy password"))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use crate::config::{AppConfig, ConfigBuilder, SharedConfig};
+    use crate::service::users::{hash_password, verify_password, UserService, MAX_PASSWORD_CHECKS};
+
+    #[test]
+    fn password_matches_its_hash() {
+        let hash = hash_password("secret").unwrap();
+
+        assert!(verify_password(&hash, "secret"));
+        assert!(!verify_password(&hash, "secret2"));
+        assert!(!verify_password("not a hash", "secret"));
+    }
+
+    #[tokio::test]
+    async fn admin_session_is_not_admin_token() {
+        let token = "a".repeat(40);
+        let config = ConfigBuilder::default().admin(&token).build();
+        let users = UserService::new(SharedConfig::new(config));
+
+        assert!(users.admin_login("wrong").await.is_none());
+        let session = users.admin_login(&token).await.unwrap();
+
+        assert_ne!(session, token);
+        assert!(users.is_admin(&session).await);
+        assert!(!users.is_admin(&token).await);
+        assert_eq!(users.user(&session).await, None);
+
+        users.logout(&session).await;
+        assert!(!users.is_admin(&session).await);
+    }
+
+    #[tokio::test]
+    async fn admin_sessions_end_when_token_changes() {
+        let config = |token: char| {
+            ConfigBuilder::default()
+                .admin(&token.to_string().repeat(40))
+                .build()
+        };
+        let shared = SharedConfig::new(config('a'));
+        let users = UserService::new(shared.clone());
+
+        let session = users.admin_login(&"a".repeat(40)).await.unwrap();
+        assert!(users.is_admin(&session).await);
+
+        shared.replace(config('b'));
+        assert!(!users.is_admin(&session).await);
+    }
+
+    #[tokio::test]
+    async fn sessions_end_when_password_changes() {
+        let config = |password: &str| -> AppConfig {
+            let mut config = ConfigBuilder::default().user("alice").build();
+            config.users[0].password = Secret::new(hash_password(password).unwrap());
+            config
+        };
+        let shared = SharedConfig::new(config("old"));
+        let users = UserService::new(shared.clone());
+
+        assert!(users.login("alice", "wrong").await.is_none());
+        let session = users.login("alice", "old").await.unwrap();
+        assert_eq!(users.user(&session).await.as_deref(), Some("alice"));
+
+        shared.replace(config("new"));
+        assert_eq!(users.user(&session).await, None);
+    }
+
+    #[tokio::test]
+    async fn logins_fail_when_too_many_passwords_are_checked() {
+        let mut config = ConfigBuilder::default().user("alice").build();
+        config.users[0].password = Secret::new(hash_password("secret").unwrap());
+        let users = UserService::new(SharedConfig::new(config));
+
+        let busy = users
+            .password_checks
+            .acquire_many(MAX_PASSWORD_CHECKS as u32)
+            .await
+            .unwrap();
+        assert!(users.login("alice", "secret").await.is_none());
+
+        drop(busy);
+        assert!(users.login("alice", "secret").await.is_some());
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use argon2::PasswordHash;
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::config::{AppConfig, CalendarConfig, FeedConfig, TokenConfig};
+use crate::service::tokens::{ConfigToken, TokenDigest};
+
+/// Tokens shorter than this are easy to guess
+pub const MIN_TOKEN_LENGTH: usize = 40;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// App can't work correctly with this config
+    Error,
+
+    /// App works, but setting is insecure or useless
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    /// Path of invalid setting, e.g. `feeds[2].tokens[1].token`
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Config is invalid:\n{}", format_issues(.0))]
+pub struct InvalidConfig(pub Vec<ConfigIssue>);
+
+fn format_issues(issues: &[ConfigIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| format!("  {}", issue))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// All problems found in config
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    /// Logs warnings and returns all errors if there are any
+    pub fn into_result(self) -> Result<(), InvalidConfig> {
+        let (errors, warnings): (Vec<_>, Vec<_>) = self
+            .issues
+            .into_iter()
+            .partition(|i| i.severity == Severity::Error);
+
+        for warning in warnings {
+            tracing::warn!("Config: {}", warning);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig(errors))
+        }
+    }
+
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.add(Severity::Error, path.into(), message.into());
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.add(Severity::Warning, path.into(), message.into());
+    }
+
+    fn add(&mut self, severity: Severity, path: String, message: String) {
+        self.issues.push(ConfigIssue {
+            severity,
+            path,
+            message,
+        });
+    }
+}
+
+impl AppConfig {
+    /// Checks whole config and reports all problems at once
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        if self.server.session_ttl == 0 {
+            report.error("server.session_ttl", "must be greater than 0");
+        }
+        if self.cache.refresh_interval == 0 {
+            report.error("cache.refresh_interval", "must be greater than 0");
+        }
+        if self.cache.ttl == 0 {
+            report.error("cache.ttl", "must be greater than 0");
+        }
+        if self.fetch.connect_timeout == 0 {
+            report.error("fetch.connect_timeout", "must be greater than 0");
+        }
+        if self.fetch.read_timeout == 0 {
+            report.error("fetch.read_timeout", "must be greater than 0");
+        }
+        if self.fetch.max_size == 0 {
+            report.error("fetch.max_size", "must be greater than 0");
+        }
+        if self.fetch.breaker_threshold == 0 {
+            report.error("fetch.breaker_threshold", "must be greater than 0");
+        }
+
+        // path of first usage of each token
+        let mut tokens: HashMap<TokenDigest, String> = HashMap::new();
+        if let Some(admin) = &self.admin {
+            validate_token(
+                &mut report,
+                &mut tokens,
+                "admin.token".to_string(),
+                admin.token.expose_secret(),
+            );
+        }
+
+        let mut users: HashMap<&str, usize> = HashMap::new();
+        for (i, user) in self.users.iter().enumerate() {
+            let path = format!("users[{}]", i);
+
+            if user.name.trim().is_empty() {
+                report.error(format!("{}.name", path), "must not be empty");
+            } else if let Some(other) = users.insert(&user.name, i) {
+                report.error(
+                    format!("{}.name", path),
+                    format!("is the same as name of users[{}]", other),
+                );
+            }
+            if PasswordHash::new(user.password.expose_secret()).is_err() {
+                report.error(
+                    format!("{}.password", path),
+                    "must be argon2 hash, see `icaliada hash-password`",
+                );
+            }
+        }
+
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for (i, feed) in self.feeds.iter().enumerate() {
+            let path = format!("feeds[{}]", i);
+
+            if feed.name.trim().is_empty() {
+                report.error(format!("{}.name", path), "must not be empty");
+            } else if let Some(other) = names.insert(&feed.name, i) {
+                report.error(
+                    format!("{}.name", path),
+                    format!("is the same as name of feeds[{}]", other),
+                );
+            }
+
+            if let Some(owner) = &feed.owner {
+                if !users.contains_key(owner.as_str()) {
+                    report.error(format!("{}.owner", path), "is not a known user");
+                }
+            }
+
+            validate_feed_tokens(&mut report, &mut tokens, &path, &feed.tokens);
+
+            if feed.calendars.is_empty() && feed.include.is_empty() {
+                report.warning(format!("{}.calendars", path), "feed has no calendars");
+            }
+
+            for (j, name) in feed.include.iter().enumerate() {
+                let path = format!("{}.include[{}]", path, j);
+                match self.find_feed(name) {
+                    None => report.error(path, "is not a known feed"),
+                    // users can't show calendars of others in their feeds
+                    Some(included) if feed.owner.is_some() && included.owner != feed.owner => {
+                        report.error(path, "is owned by another user")
+                    }
+                    Some(_) => {}
+                }
+            }
+            if let Some(cycle) = find_cycle(self, feed) {
+                report.error(
+                    format!("{}.include", path),
+                    format!("feeds include each other: {}", cycle.join(" -> ")),
+                );
+            }
+
+            let mut urls: HashMap<&str, usize> = HashMap::new();
+            for (j, calendar) in feed.calendars.iter().enumerate() {
+                let path = format!("{}.calendars[{}]", path, j);
+                let url = calendar.url.expose_secret();
+
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    report.error(format!("{}.url", path), "must be http or https url");
+                } else if let Some(other) = urls.insert(url, j) {
+                    report.warning(
+                        format!("{}.url", path),
+                        format!(
+                            "is the same as url of calendars[{}], calendar is shown once",
+                            other
+                        ),
+                    );
+                }
+
+                validate_calendar_overrides(&mut report, &path, calendar);
+            }
+        }
+
+        report
+    }
+}
+
+/// Checks overrides of global settings, zero values are rejected the same way as global ones
+fn validate_calendar_overrides(
+    report: &mut ValidationReport,
+    calendar_path: &str,
+    calendar: &CalendarConfig,
+) {
+    let overrides = [
+        ("refresh_interval", calendar.refresh_interval),
+        ("ttl", calendar.ttl),
+        ("connect_timeout", calendar.connect_timeout),
+        ("read_timeout", calendar.read_timeout),
+        ("max_size", calendar.max_size),
+    ];
+    for (name, value) in overrides {
+        if value == Some(0) {
+            report.error(
+                format!("{}.{}", calendar_path, name),
+                "must be greater than 0",
+            );
+        }
+    }
+}
+
+/// Returns names of feeds on include path which leads back to given feed
+fn find_cycle<'a>(config: &'a AppConfig, feed: &'a FeedConfig) -> Option<Vec<&'a str>> {
+    fn visit<'a>(
+        config: &'a AppConfig,
+        feed: &'a FeedConfig,
+        start: &str,
+        visited: &mut HashSet<&'a str>,
+        path: &mut Vec<&'a str>,
+    ) -> bool {
+        for name in &feed.include {
+            path.push(name);
+            if name == start {
+                return true;
+            }
+            // feeds explored before don't lead back to start
+            if visited.insert(name) {
+                if let Some(included) = config.find_feed(name) {
+                    if visit(config, included, start, visited, path) {
+                        return true;
+                    }
+                }
+            }
+            path.pop();
+        }
+        false
+    }
+
+    let mut path = vec![feed.name.as_str()];
+    visit(config, feed, &feed.name, &mut HashSet::new(), &mut path).then_some(path)
+}
+
+fn validate_feed_tokens(
+    report: &mut ValidationReport,
+    tokens: &mut HashMap<TokenDigest, String>,
+    feed_path: &str,
+    feed_tokens: &[TokenConfig],
+) {
+    if feed_tokens.is_empty() {
+        report.error(format!("{}.tokens", feed_path), "feed has no tokens");
+        return;
+    }
+
+    let now = Utc::now();
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    for (i, token) in feed_tokens.iter().enumerate() {
+        let path = format!("{}.tokens[{}]", feed_path, i);
+
+        if token.label.trim().is_empty() {
+            report.error(format!("{}.label", path), "must not be empty");
+        } else if let Some(other) = labels.insert(&token.label, i) {
+            report.error(
+                format!("{}.label", path),
+                format!("is the same as label of tokens[{}]", other),
+            );
+        }
+
+        validate_token(
+            report,
+            tokens,
+            format!("{}.token", path),
+            token.token.expose_secret(),
+        );
+
+        if !token.revoked && token.is_expired(now) {
+            report.warning(format!("{}.expires_at", path), "token is expired");
+        }
+    }
+
+    if feed_tokens
+        .iter()
+        .all(|token| token.revoked || token.is_expired(now))
+    {
+        report.warning(
+            format!("{}.tokens", feed_path),
+            "all tokens are revoked or expired",
+        );
+    }
+}
+
+/// Checks that token is long enough and not used anywhere else
+fn validate_token(
+    report: &mut ValidationReport,
+    tokens: &mut HashMap<TokenDigest, String>,
+    path: String,
+    value: &str,
+) {
+    if value.is_empty() {
+        report.error(path, "must not be empty");
+        return;
+    }
+
+    let token = match ConfigToken::parse(value) {
+        Ok(token) => token,
+        Err(err) => {
+            report.error(path, err.to_string());
+            return;
+        }
+    };
+
+    if let Some(other) = tokens.get(&token.digest) {
+        report.error(path, format!("is the same as {}", other));
+        return;
+    }
+
+    // length of hashed tokens is unknown, so they are expected to be generated
+    if !token.hashed && value.chars().count() < MIN_TOKEN_LENGTH {
+        report.warning(
+            path.clone(),
+            format!(
+                "is shorter than {} characters and easy to guess",
+                MIN_TOKEN_LENGTH
+            ),
+        );
+    }
+    tokens.insert(token.digest, path);
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::config::{ConfigBuilder, FeedBuilder};
+    use crate::service::tokens::hashed_token;
+    use crate::service::validation::Severity;
+
+    const TOKEN_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const TOKEN_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const TOKEN_C: &str = "cccccccccccccccccccccccccccccccccccccccccccc";
+    const TOKEN_D: &str = "dddddddddddddddddddddddddddddddddddddddddddd";
+
+    #[rstest]
+    #[case::valid(FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a"]).yaml(), vec![])]
+    #[case::same_tokens(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_A, &["https://a"]).yaml(),
+        vec![(Severity::Error, "feeds[0].tokens[1].token")]
+    )]
+    #[case::same_hashed_tokens(
+        FeedBuilder::new("a", TOKEN_A, &hashed_token(TOKEN_A), &["https://a"]).yaml(),
+        vec![(Severity::Error, "feeds[0].tokens[1].token")]
+    )]
+    #[case::invalid_digest(
+        FeedBuilder::new("a", TOKEN_A, "sha256:abc", &["https://a"]).yaml(),
+        vec![(Severity::Error, "feeds[0].tokens[1].token")]
+    )]
+    #[case::short_token(
+        FeedBuilder::new("a", TOKEN_A, "short", &["https://a"]).yaml(),
+        vec![(Severity::Warning, "feeds[0].tokens[1].token")]
+    )]
+    #[case::no_calendars(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &[]).yaml(),
+        vec![(Severity::Warning, "feeds[0].calendars")]
+    )]
+    #[case::invalid_url(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a", "ftp://b"]).yaml(),
+        vec![(Severity::Error, "feeds[0].calendars[1].url")]
+    )]
+    #[case::calendar_overrides(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a"]).yaml()
+            .replace("https://a\n", "https://a\n        refresh_interval: 60\n        ttl: 120\n"),
+        vec![]
+    )]
+    #[case::zero_calendar_overrides(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a", "https://b"]).yaml().replace(
+            "https://b\n",
+            "https://b\n        refresh_interval: 0\n        ttl: 0\n        connect_timeout: 0\n        read_timeout: 0\n        max_size: 0\n"
+        ),
+        vec![
+            (Severity::Error, "feeds[0].calendars[1].refresh_interval"),
+            (Severity::Error, "feeds[0].calendars[1].ttl"),
+            (Severity::Error, "feeds[0].calendars[1].connect_timeout"),
+            (Severity::Error, "feeds[0].calendars[1].read_timeout"),
+            (Severity::Error, "feeds[0].calendars[1].max_size"),
+        ]
+    )]
+    #[case::duplicates_across_feeds(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a"]).yaml()
+            + &FeedBuilder::new("b", TOKEN_C, TOKEN_D, &["https://a"]).yaml()
+            + &FeedBuilder::new("a", TOKEN_B, TOKEN_C, &["https://a"]).yaml(),
+        vec![
+            (Severity::Error, "feeds[2].name"),
+            (Severity::Error, "feeds[2].tokens[0].token"),
+            (Severity::Error, "feeds[2].tokens[1].token"),
+        ]
+    )]
+    #[case::token_list(
+        format!(
+            "  - name: a\n    tokens:\n{}{}{}    calendars:\n      - url: https://a\n",
+            format!("      - label: team\n        token: {}\n        privacy: private\n", TOKEN_A),
+            format!("      - label: team\n        token: {}\n        privacy: public\n", TOKEN_B),
+            format!(
+                "      - label: old\n        token: {}\n        privacy: public\n        expires_at: 2020-01-01T00:00:00Z\n",
+                TOKEN_C
+            ),
+        ),
+        vec![
+            (Severity::Error, "feeds[0].tokens[1].label"),
+            (Severity::Warning, "feeds[0].tokens[2].expires_at"),
+        ]
+    )]
+    #[case::unknown_owner(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a"]).owner("bob").yaml(),
+        vec![(Severity::Error, "feeds[0].owner")]
+    )]
+    #[case::include_cycle(
+        FeedBuilder::new("a", TOKEN_A, TOKEN_B, &["https://a"]).include(&["b"]).yaml()
+            + &FeedBuilder::new("b", TOKEN_C, TOKEN_D, &[]).include(&["a", "c"]).yaml(),
+        vec![
+            (Severity::Error, "feeds[0].include"),
+            (Severity::Error, "feeds[1].include[1]"),
+            (Severity::Error, "feeds[1].include"),
+        ]
+    )]
+    fn reports_issues(#[case] feeds: String, #[case] expected: Vec<(Severity, &str)>) {
+        let report = ConfigBuilder::default().feeds(&feeds).build().validate();
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|i| (i.severity, i.path.as_str()))
+            .collect();
+
+        assert_eq!(issues, expected, "{:?}", report.issues);
+    }
+}
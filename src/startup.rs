@@ -5,15 +5,21 @@ use std::pin::Pin;
 use futures::Future;
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
 use crate::routes;
-use crate::service::config::AppConfig;
+use crate::service::admin::{self, AdminService};
+use crate::service::config::{AppConfig, SharedConfig};
 use crate::service::feeds::FeedService;
+use crate::service::store::FeedStore;
+use crate::service::users::UserService;
 
 pub struct Application {
     port: u16,
     server: Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>,
     shutdown_hook: Option<oneshot::Sender<()>>,
+    feed_service: FeedService,
+    reload_task: Option<JoinHandle<()>>,
 }
 
 impl Debug for Application {
@@ -33,9 +39,22 @@ impl Application {
 
         tracing::info!("Listening on port {}", port);
 
-        let feed_service = FeedService::new(&config);
+        let store = FeedStore::from_config(&config);
+        let stored = match &store {
+            Some(store) => store.load().await?,
+            None => vec![],
+        };
+        let shared = SharedConfig::new(admin::merge_feeds(&config, &stored)?);
 
-        let router = routes::create_router(config, feed_service);
+        let feed_service = FeedService::new(shared.clone())?;
+        feed_service.load_disk_cache().await;
+        feed_service.start_refresh_tasks();
+        let admin_service = AdminService::new(config, store, stored, feed_service.clone());
+        let user_service = UserService::new(shared.clone());
+        let reload_task = spawn_reload_task(admin_service.clone());
+
+        let router =
+            routes::create_router(shared, feed_service.clone(), admin_service, user_service);
 
         let serve = axum::serve(listener, router.into_make_service());
         let (tx, rx) = tokio::sync::oneshot::channel::<()>();
@@ -47,6 +66,8 @@ impl Application {
             port,
             server: Box::pin(graceful.into_future()),
             shutdown_hook: Some(tx),
+            feed_service,
+            reload_task,
         })
     }
 
@@ -59,6 +80,50 @@ impl Application {
     }
 
     pub async fn wait_finish(self) -> std::io::Result<()> {
-        self.server.await
+        let result = self.server.await;
+        self.feed_service.stop_refresh_tasks();
+        if let Some(task) = self.reload_task {
+            task.abort();
+        }
+        result
     }
 }
+
+/// Reloads config on SIGHUP. Invalid config is rejected and old one is kept
+#[cfg(unix)]
+fn spawn_reload_task(admin_service: AdminService) -> Option<JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            tracing::error!(
+                "Failed to listen for SIGHUP, config reload is disabled: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    Some(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading config");
+            let result = match AppConfig::load() {
+                Ok(config) => admin_service
+                    .reload(config)
+                    .await
+                    .map_err(anyhow::Error::from),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => tracing::info!("Config reloaded"),
+                Err(err) => tracing::error!("Failed to reload config: {:#}", err),
+            }
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+fn spawn_reload_task(_admin_service: AdminService) -> Option<JoinHandle<()>> {
+    None
+}